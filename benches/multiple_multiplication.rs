@@ -1,8 +1,14 @@
+#![allow(
+    clippy::explicit_counter_loop,
+    clippy::needless_borrows_for_generic_args
+)]
+
 mod utils;
 
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::io;
 use std::time::SystemTime;
+use ultrametric_matrix_tools::na::DMatrix;
 use ultrametric_matrix_tools::UltrametricTree;
 
 criterion_group!(benches, benchmark_multiple);
@@ -10,7 +16,7 @@ criterion_main!(benches);
 
 const MATRIX_SIZES: [usize; 8] = [10, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
 const NUM_SAMPLES: u32 = 10;
-const HEADER_MULTIPLE: [&str; 17] = [
+const HEADER_MULTIPLE: [&str; 19] = [
     "pos",
     "size",
     "iterations",
@@ -18,6 +24,8 @@ const HEADER_MULTIPLE: [&str; 17] = [
     "tree_gen_std",
     "tree_mult_mean",
     "tree_mult_std",
+    "tree_mult_matrix_mean",
+    "tree_mult_matrix_std",
     "complete_tree_mult_mean",
     "complete_tree_mult_std",
     "prune_tree_mean",
@@ -41,6 +49,7 @@ fn benchmark_multiple(_c: &mut Criterion) {
     for &size in MATRIX_SIZES.iter() {
         let mut tree_gen_times: Vec<f64> = Vec::new();
         let mut tree_mult_times: Vec<f64> = Vec::new();
+        let mut tree_mult_matrix_times: Vec<f64> = Vec::new();
         let mut complete_tree_mult_times: Vec<f64> = Vec::new();
         let mut normal_mult_times: Vec<f64> = Vec::new();
         let mut prune_tree_times: Vec<f64> = Vec::new();
@@ -58,6 +67,11 @@ fn benchmark_multiple(_c: &mut Criterion) {
                 root.mult(&vector);
             }
             let duration_tree_mult = start_tree_mult.elapsed().unwrap();
+            let vectors = DMatrix::from_fn(size, NUM_ITERATIONS, |row, _| vector[row]);
+            let start_tree_mult_matrix = SystemTime::now();
+            root.mult_matrix(&vectors);
+            let duration_tree_mult_matrix = start_tree_mult_matrix.elapsed().unwrap();
+            tree_mult_matrix_times.push(duration_tree_mult_matrix.as_secs_f64());
             tree_gen_times.push(duration_tree_gen.as_secs_f64());
             tree_mult_times.push(duration_tree_mult.as_secs_f64());
             complete_tree_mult_times
@@ -89,6 +103,7 @@ fn benchmark_multiple(_c: &mut Criterion) {
 
         let tree_gen_mean = tree_gen_times.iter().sum::<f64>() / NUM_SAMPLES as f64;
         let tree_mult_mean = tree_mult_times.iter().sum::<f64>() / NUM_SAMPLES as f64;
+        let tree_mult_matrix_mean = tree_mult_matrix_times.iter().sum::<f64>() / NUM_SAMPLES as f64;
         let complete_tree_mult_mean =
             complete_tree_mult_times.iter().sum::<f64>() / NUM_SAMPLES as f64;
         let prune_tree_mean = prune_tree_times.iter().sum::<f64>() / NUM_SAMPLES as f64;
@@ -107,6 +122,11 @@ fn benchmark_multiple(_c: &mut Criterion) {
             .map(|&val| ((val - tree_mult_mean).powi(2)))
             .sum::<f64>()
             / NUM_SAMPLES as f64;
+        let tree_mult_matrix_std = tree_mult_matrix_times
+            .iter()
+            .map(|&val| ((val - tree_mult_matrix_mean).powi(2)))
+            .sum::<f64>()
+            / NUM_SAMPLES as f64;
         let complete_tree_mult_std = complete_tree_mult_times
             .iter()
             .map(|&val| ((val - complete_tree_mult_mean).powi(2)))
@@ -141,6 +161,8 @@ fn benchmark_multiple(_c: &mut Criterion) {
             tree_gen_std.to_string(),
             tree_mult_mean.to_string(),
             tree_mult_std.to_string(),
+            tree_mult_matrix_mean.to_string(),
+            tree_mult_matrix_std.to_string(),
             complete_tree_mult_mean.to_string(),
             complete_tree_mult_std.to_string(),
             prune_tree_mean.to_string(),
@@ -1,3 +1,8 @@
+#![allow(
+    clippy::explicit_counter_loop,
+    clippy::needless_borrows_for_generic_args
+)]
+
 mod utils;
 
 use criterion::{criterion_group, criterion_main, Criterion};
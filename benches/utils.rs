@@ -1,3 +1,5 @@
+#![allow(clippy::needless_return, clippy::int_plus_one)]
+
 use nalgebra::{DMatrix, DVector};
 use rand::prelude::*;
 
@@ -12,3 +12,7 @@ matrix[3, 0] = 2.0
 
 print("This matrix should not be ultrametric (false):",
       ut.utils.is_ultrametric(matrix))
+
+report = ut.utils.check_ultrametric(matrix)
+print("First violation:", report["violation"],
+      "maximal violation:", report["max_violation"])
@@ -19,4 +19,9 @@ fn main() {
         "This matrix should not be ultrametric (false): {}",
         is_ultrametric(&matrix)
     );
+    let report = check_ultrametric(&matrix);
+    println!(
+        "First violation: {:?}, maximal violation: {}",
+        report.violation, report.max_violation
+    );
 }
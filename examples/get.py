@@ -7,3 +7,5 @@ matrix = np.array([[0.0, 1.0, 3.0, 1.0], [1.0, 3.0, 1.0, 1.0], [
 tree = UltrametricTree(matrix)
 print("Element of the matrix with index (0, 2):", tree.get(0, 2))
 print("Requesting element outside the matrix result:", tree.get(0, 4))
+print("Elements of the matrix with indices (0, 2), (1, 3) and (0, 4):",
+      tree.get_many([(0, 2), (1, 3), (0, 4)]))
@@ -18,4 +18,8 @@ fn main() {
         "Requesting element outside the matrix result: {:?}",
         tree.get(0, 4)
     );
+    println!(
+        "Elements of the matrix with indices (0, 2), (1, 3) and (0, 4): {:?}",
+        tree.get_many(&[(0, 2), (1, 3), (0, 4)])
+    );
 }
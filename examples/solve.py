@@ -0,0 +1,10 @@
+from ultrametric_matrix_tools import UltrametricTree
+import numpy as np
+
+matrix = np.array([[7.0, 1.0, 3.0, 1.0], [1.0, 5.0, 1.0, 1.0], [
+    3.0, 1.0, 8.0, 1.0], [1.0, 1.0, 1.0, 9.0]])
+b = np.array([3.0, 2.0, 6.0, 7.0])
+
+tree = UltrametricTree(matrix)
+x = tree.solve(b)
+print("Solution x to the equation system Ax=b:", x)
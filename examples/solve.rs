@@ -0,0 +1,17 @@
+use ultrametric_matrix_tools::na::{DMatrix, DVector};
+use ultrametric_matrix_tools::UltrametricTree;
+
+fn main() {
+    let matrix = DMatrix::from_vec(
+        4,
+        4,
+        vec![
+            7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0,
+        ],
+    );
+    let b = DVector::from_vec(vec![3.0, 2.0, 6.0, 7.0]);
+
+    let tree = UltrametricTree::from_matrix(&matrix);
+    let x = tree.solve(&b).unwrap();
+    println!("Solution x to the equation system Ax=b: {}", x);
+}
@@ -0,0 +1,412 @@
+//! Compact binary format of the `UltrametricTree`, which can be used through a memory map without deserialization.
+//!
+//! The format consists of a header of 32 bytes followed by five arrays, where all numbers are stored in little-endian byte order:
+//!
+//! | Offset | Content |
+//! |---|---|
+//! | `0` | Magic bytes [`MAGIC`] |
+//! | `8` | Format version [`BINARY_FORMAT_VERSION`] as `u32` |
+//! | `12` | Reserved `u32`, which is zero |
+//! | `16` | Size `n` of the matrix as `u64` |
+//! | `24` | Number of vertices `m` as `u64` |
+//! | `32` | Levels of the vertices in pre-order as `m` times `f64` |
+//! | `32 + 8 m` | Subtree ends of the vertices as `m` times `u64` |
+//! | `32 + 16 m` | Starts of the `partition_leaves` of the vertices as `m` times `u64` |
+//! | `32 + 24 m` | Ends of the `partition` of the vertices as `m` times `u64` |
+//! | `32 + 32 m` | Permutation of the indices as `n` times `u64` |
+//!
+//! The subtree ends are the child offsets of the tree: the first child of the vertex `id` is the vertex `id + 1` and the next sibling of a child is at the subtree end of the child. The `partition_leaves` of a vertex are the indices of the permutation from its leaf start to its partition end. Since all elements have 8 bytes and the header has 32 bytes, all arrays are aligned if the data is aligned to 8 bytes, which holds for memory maps. Thus, the arrays are used in place by [`UltrametricTreeView`] and [`MappedUltrametricTree`].
+
+use crate::error::UltrametricError;
+use crate::lca::LcaIndex;
+use crate::ultrametric_tree::{vector_from_py, UltrametricTree, Vertex};
+use memmap2::Mmap;
+use nalgebra::DVector;
+use numpy::PyArray1;
+use numpy::PyReadonlyArrayDyn;
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Magic bytes at the start of the binary format.
+pub const MAGIC: [u8; 8] = *b"UMTREE\0\0";
+
+/// Version of the binary format that is written by [`write_binary`](UltrametricTree::write_binary).
+pub const BINARY_FORMAT_VERSION: u32 = 1;
+
+/// Size of the header of the binary format in bytes.
+const HEADER_SIZE: usize = 32;
+
+/// Read-only view of an `UltrametricTree` in the compact binary format, whose arrays are borrowed from the underlying bytes.
+///
+/// The view supports the multiplication with a vector without copying the tree. The vertices are stored in pre-order as in the [`UltrametricTree`].
+#[derive(Clone, Copy)]
+pub struct UltrametricTreeView<'a> {
+    /// Levels of the vertices
+    levels: &'a [f64],
+    /// Subtree ends of the vertices
+    subtree_ends: &'a [u64],
+    /// Starts of the `partition_leaves` of the vertices
+    leaf_starts: &'a [u64],
+    /// Ends of the `partition` of the vertices
+    partition_ends: &'a [u64],
+    /// Permutation of the indices
+    permutation: &'a [u64],
+}
+
+/// Construct the error for malformed data in the binary format.
+fn invalid_binary(reason: &'static str) -> UltrametricError {
+    return UltrametricError::InvalidBinary { reason };
+}
+
+/// Construct the error for data that violates an invariant of the tree.
+fn invalid_tree(reason: &'static str) -> UltrametricError {
+    return UltrametricError::InvalidTree { reason };
+}
+
+/// Reinterpret `len` elements of 8 bytes starting at `offset` in `bytes` as a slice.
+///
+/// The caller has to ensure that `bytes` is aligned to 8 bytes, contains the range of the slice and that the target is little-endian. `T` has to be `f64` or `u64`, for which every bit pattern is valid.
+fn cast_slice<T>(bytes: &[u8], offset: usize, len: usize) -> &[T] {
+    let data = bytes[offset..offset + 8 * len].as_ptr() as *const T;
+    return unsafe { std::slice::from_raw_parts(data, len) };
+}
+
+impl<'a> UltrametricTreeView<'a> {
+    /// Construct a view of a tree in the compact binary format and check the format and the invariants of the tree.
+    ///
+    /// The bytes have to be aligned to 8 bytes, which holds for memory maps and usually for allocated buffers, and the platform has to be little-endian. If the header or the length of the data is invalid or one of these requirements is not met, then [`UltrametricError::InvalidBinary`] is returned. If the data violates an invariant of the tree, see [`MappedUltrametricTree`], then [`UltrametricError::InvalidTree`] is returned. The check needs linear time but does not copy the tree.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let vector = ultrametric_matrix_tools::na::DVector::from_vec(vec![4.0, 2.0, 7.0, 5.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let mut bytes = Vec::new();
+    /// tree.write_binary(&mut bytes).unwrap();
+    /// let view = ultrametric_matrix_tools::binary::UltrametricTreeView::from_bytes(&bytes).unwrap();
+    ///
+    /// assert_eq!(view.mult(&vector).unwrap(), tree.mult(&vector));
+    /// ```
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, UltrametricError> {
+        let view = UltrametricTreeView::from_bytes_unchecked(bytes)?;
+        view.check_invariants()?;
+        return Ok(view);
+    }
+
+    /// Construct a view of a tree in the compact binary format and only check the header and the length of the data.
+    fn from_bytes_unchecked(bytes: &'a [u8]) -> Result<Self, UltrametricError> {
+        if bytes.len() < HEADER_SIZE || bytes[0..8] != MAGIC {
+            return Err(invalid_binary("the header is missing"));
+        }
+        if cfg!(target_endian = "big") {
+            return Err(invalid_binary("big-endian platforms are not supported"));
+        }
+        if bytes.as_ptr().align_offset(8) != 0 {
+            return Err(invalid_binary("the data is not aligned to 8 bytes"));
+        }
+        let read_u64 = |offset: usize| {
+            let mut number = [0u8; 8];
+            number.copy_from_slice(&bytes[offset..offset + 8]);
+            return usize::try_from(u64::from_le_bytes(number)).ok();
+        };
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[8..12]);
+        if u32::from_le_bytes(version) != BINARY_FORMAT_VERSION {
+            return Err(invalid_binary("the format version is not supported"));
+        }
+        let size = read_u64(16);
+        let num_vertices = read_u64(24);
+        let expected_len = size
+            .zip(num_vertices)
+            .and_then(|(size, num_vertices)| num_vertices.checked_mul(4)?.checked_add(size))
+            .and_then(|num_elements| num_elements.checked_mul(8)?.checked_add(HEADER_SIZE));
+        if expected_len != Some(bytes.len()) {
+            return Err(invalid_binary(
+                "the length of the data does not match the header",
+            ));
+        }
+        let size = size.unwrap();
+        let num_vertices = num_vertices.unwrap();
+        return Ok(UltrametricTreeView {
+            levels: cast_slice(bytes, HEADER_SIZE, num_vertices),
+            subtree_ends: cast_slice(bytes, HEADER_SIZE + 8 * num_vertices, num_vertices),
+            leaf_starts: cast_slice(bytes, HEADER_SIZE + 16 * num_vertices, num_vertices),
+            partition_ends: cast_slice(bytes, HEADER_SIZE + 24 * num_vertices, num_vertices),
+            permutation: cast_slice(bytes, HEADER_SIZE + 32 * num_vertices, size),
+        });
+    }
+
+    /// Check the invariants of the tree, which are the same as for loading an [`UltrametricTree`] with `serde`.
+    ///
+    /// The starts of the partitions are not stored, so they are reconstructed top-down from the partitions of the children.
+    fn check_invariants(&self) -> Result<(), UltrametricError> {
+        let size = self.size();
+        let num_vertices = self.levels.len() as u64;
+        let mut is_seen = vec![false; size];
+        for &idx in self.permutation.iter() {
+            if idx >= size as u64 || is_seen[idx as usize] {
+                return Err(invalid_tree(
+                    "the permutation is not a permutation of the indices",
+                ));
+            }
+            is_seen[idx as usize] = true;
+        }
+        if num_vertices == 0 {
+            if size > 0 {
+                return Err(invalid_tree("the tree has indices but no vertices"));
+            }
+            return Ok(());
+        }
+        if self.subtree_ends[0] != num_vertices || self.partition_ends[0] != size as u64 {
+            return Err(invalid_tree(
+                "the root does not contain all vertices and indices",
+            ));
+        }
+        let mut partition_starts: Vec<u64> = vec![0; self.levels.len()];
+        for id in 0..self.levels.len() {
+            if self.levels[id].is_nan() {
+                return Err(invalid_tree("the level of a vertex is NaN"));
+            }
+            if self.leaf_starts[id] < partition_starts[id]
+                || self.partition_ends[id] < self.leaf_starts[id]
+            {
+                return Err(invalid_tree(
+                    "the partition of a vertex is not a valid range",
+                ));
+            }
+            let subtree_end = self.subtree_ends[id];
+            let mut child = id as u64 + 1;
+            let mut partition_end = partition_starts[id];
+            while child < subtree_end {
+                let child_subtree_end = self.subtree_ends[child as usize];
+                if child_subtree_end <= child || child_subtree_end > subtree_end {
+                    return Err(invalid_tree(
+                        "the subtree of a vertex is not contained in its parent",
+                    ));
+                }
+                partition_starts[child as usize] = partition_end;
+                partition_end = self.partition_ends[child as usize];
+                child = child_subtree_end;
+            }
+            if partition_end != self.leaf_starts[id] {
+                return Err(invalid_tree(
+                    "the partitions of the children of a vertex are not contiguous",
+                ));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Number of rows and columns of the matrix represented by the tree.
+    pub fn size(&self) -> usize {
+        return self.permutation.len();
+    }
+
+    /// Iterator over the children of the vertex `id`.
+    fn children(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        let subtree_end = self.subtree_ends[id] as usize;
+        let first_child = if id + 1 < subtree_end {
+            Some(id + 1)
+        } else {
+            None
+        };
+        return std::iter::successors(first_child, move |&child| {
+            let next_child = self.subtree_ends[child] as usize;
+            if next_child < subtree_end {
+                Some(next_child)
+            } else {
+                None
+            }
+        });
+    }
+
+    /// Indices, where the vertex `id` is a leaf.
+    fn partition_leaves(&self, id: usize) -> &'a [u64] {
+        return &self.permutation[self.leaf_starts[id] as usize..self.partition_ends[id] as usize];
+    }
+
+    /// Calculate the product of the ultrametric matrix represented by the tree and a vector.
+    ///
+    /// The multiplication is done in the same two steps as in [`mult`](UltrametricTree::mult) directly on the borrowed arrays. Only the partial products of the vertices are stored in temporary vectors. If the size of `vector` differs from the size of the matrix, then [`UltrametricError::DimensionMismatch`] is returned.
+    pub fn mult(&self, vector: &DVector<f64>) -> Result<DVector<f64>, UltrametricError> {
+        if vector.nrows() != self.size() {
+            return Err(UltrametricError::DimensionMismatch {
+                expected: self.size(),
+                found: vector.nrows(),
+            });
+        }
+        let num_vertices = self.levels.len();
+        let mut sums: Vec<f64> = vec![0.; num_vertices];
+        for id in (0..num_vertices).rev() {
+            let mut sum = 0.;
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                sum += vector[leaf_idx as usize];
+            }
+            for child in self.children(id) {
+                sum += sums[child];
+            }
+            sums[id] = sum;
+        }
+
+        let mut product = DVector::<f64>::zeros(vector.nrows());
+        let mut prev_sums: Vec<f64> = vec![0.; num_vertices];
+        let mut parent_levels: Vec<f64> = vec![0.; num_vertices];
+        for id in 0..num_vertices {
+            let sum = prev_sums[id] + (self.levels[id] - parent_levels[id]) * sums[id];
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                product[leaf_idx as usize] = sum;
+            }
+            for child in self.children(id) {
+                prev_sums[child] = sum;
+                parent_levels[child] = self.levels[id];
+            }
+        }
+        return Ok(product);
+    }
+
+    /// Copy the tree into an owned `UltrametricTree`, which supports all operations.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let mut bytes = Vec::new();
+    /// tree.write_binary(&mut bytes).unwrap();
+    /// let view = ultrametric_matrix_tools::binary::UltrametricTreeView::from_bytes(&bytes).unwrap();
+    ///
+    /// assert_eq!(view.to_tree().to_matrix(), matrix);
+    /// ```
+    pub fn to_tree(&self) -> UltrametricTree {
+        let mut vertices: Vec<Vertex> = vec![Vertex::default(); self.levels.len()];
+        let mut leaf_vertices: Vec<usize> = vec![0; self.size()];
+        for id in 0..self.levels.len() {
+            let mut partition_start = vertices[id].partition_start;
+            for child in self.children(id) {
+                vertices[child].partition_start = partition_start;
+                partition_start = self.partition_ends[child] as usize;
+            }
+            vertices[id].level = self.levels[id];
+            vertices[id].subtree_end = self.subtree_ends[id] as usize;
+            vertices[id].leaf_start = self.leaf_starts[id] as usize;
+            vertices[id].partition_end = self.partition_ends[id] as usize;
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                leaf_vertices[leaf_idx as usize] = id;
+            }
+        }
+        let tree = UltrametricTree {
+            lca_index: LcaIndex::new(&vertices),
+            vertices,
+            permutation: self.permutation.iter().map(|&idx| idx as usize).collect(),
+            leaf_vertices,
+        };
+        return tree;
+    }
+}
+
+/// `UltrametricTree` in the compact binary format that is loaded through a read-only memory map.
+///
+/// The file is not deserialized, instead the arrays of the tree are used in place through an [`UltrametricTreeView`]. Thus, a large tree can be used immediately after it is opened and multiple processes that map the same file share its memory. When the file is opened, the format and the invariants of the tree are checked in linear time: the permutation contains each index exactly once, the levels are not NaN, the subtree of each vertex is a non-empty range of vertices that is contained in the subtree of its parent and the `partition` of each vertex consists of the partitions of its children followed by its `partition_leaves`.
+#[pyclass]
+pub struct MappedUltrametricTree {
+    /// Memory map of the file
+    mmap: Mmap,
+}
+
+impl MappedUltrametricTree {
+    /// Open the file at `path` that contains a tree in the compact binary format.
+    ///
+    /// Errors of the file system are returned as they are. If the file is not a valid tree, see [`UltrametricTreeView::from_bytes`], then an error of the kind [`io::ErrorKind::InvalidData`] is returned that contains the [`UltrametricError`]. The file must not be modified while it is mapped.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let vector = ultrametric_matrix_tools::na::DVector::from_vec(vec![4.0, 2.0, 7.0, 5.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let path = std::env::temp_dir().join("ultrametric_tree_doc.bin");
+    /// tree.write_binary(std::fs::File::create(&path).unwrap()).unwrap();
+    /// let mapped = ultrametric_matrix_tools::binary::MappedUltrametricTree::open(&path).unwrap();
+    ///
+    /// assert_eq!(mapped.view().mult(&vector).unwrap(), tree.mult(&vector));
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        UltrametricTreeView::from_bytes(&mmap)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        return Ok(MappedUltrametricTree { mmap });
+    }
+
+    /// View of the mapped tree, which is constructed in constant time.
+    pub fn view(&self) -> UltrametricTreeView<'_> {
+        return UltrametricTreeView::from_bytes_unchecked(&self.mmap).unwrap();
+    }
+}
+
+#[pymethods]
+impl MappedUltrametricTree {
+    /// Python wrapper for [`open`](MappedUltrametricTree::open).
+    #[staticmethod]
+    #[pyo3(name = "open")]
+    pub fn open_py(path: &str) -> PyResult<Self> {
+        return Ok(MappedUltrametricTree::open(path)?);
+    }
+
+    /// Python wrapper for [`size`](UltrametricTreeView::size).
+    #[pyo3(name = "size")]
+    pub fn size_py(&self) -> usize {
+        return self.view().size();
+    }
+
+    /// Python wrapper for [`mult`](UltrametricTreeView::mult).
+    ///
+    /// A `ValueError` is raised if the vector is not a 1-dimensional array of the size of the matrix.
+    #[pyo3(name = "mult")]
+    pub fn mult_py<'py>(
+        &self,
+        py: Python<'py>,
+        py_vector: PyReadonlyArrayDyn<f64>,
+    ) -> PyResult<&'py PyArray1<f64>> {
+        let vector = vector_from_py(&py_vector)?;
+        let product = self.view().mult(&vector)?;
+        let py_product = PyArray1::from_vec(py, product.data.as_vec().clone());
+        return Ok(py_product);
+    }
+
+    /// Python wrapper for [`to_tree`](UltrametricTreeView::to_tree).
+    #[pyo3(name = "to_tree")]
+    pub fn to_tree_py(&self) -> UltrametricTree {
+        return self.view().to_tree();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::DMatrix;
+
+    #[test]
+    fn mult_dimension_mismatch() {
+        let matrix = DMatrix::from_vec(3, 3, vec![4.0, 1.0, 3.0, 1.0, 4.0, 1.0, 3.0, 1.0, 4.0]);
+        let tree = UltrametricTree::from_matrix(&matrix);
+        let mut bytes = Vec::new();
+        tree.write_binary(&mut bytes).unwrap();
+        let view = UltrametricTreeView::from_bytes(&bytes).unwrap();
+        let vector = DVector::from_vec(vec![1.0, 2.0, 3.0]);
+        assert_eq!(view.mult(&vector).unwrap(), tree.mult(&vector));
+        assert_eq!(
+            view.mult(&DVector::zeros(2)).err(),
+            Some(UltrametricError::DimensionMismatch {
+                expected: 3,
+                found: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,657 @@
+//! Eigenvalues and eigenvectors of an `UltrametricTree` from the elimination of its shifted matrix.
+//!
+//! The submatrix of a vertex minus the level of its parent is `D + weight * 1 * 1^T`, where the block diagonal matrix `D` consists of the submatrices of the children and zero for the leaves of the vertex. For a shift `x`, the matrix determinant lemma factors `det(D + weight * 1 * 1^T - x * I)` into `det(D - x * I)` and `-weight * pivot` with the pivot `-1 / weight - 1^T (D - x * I)^(-1) 1`, where `1^T (D - x * I)^(-1) 1` is accumulated from the children by the Sherman-Morrison formula. Thus, a single traversal of the tree in linear time yields the number of eigenvalues smaller than `x` from the signs of the pivots and the first two logarithmic derivatives of the characteristic polynomial. The shifted equation systems are solved in linear time by the elimination of [`inverse`](crate::UltrametricTree::inverse), which chooses the pivots among the children of each vertex.
+//!
+//! Some eigenvectors are known explicitly. The leaves of a vertex span eigenvectors with eigenvalue zero that sum to zero on these leaves. Similarly, children without children of their own that have the same level and the same number of leaves span eigenvectors that are constant on each of these children and sum to zero. The remaining eigenvalues are the roots of the reduced characteristic polynomial, whose eigenvectors are constant on these groups of indices.
+
+use crate::ultrametric_tree::UltrametricTree;
+use nalgebra::{DMatrix, DVector, Dynamic, SymmetricEigen};
+use rand::prelude::*;
+
+/// Smallest absolute value of a pivot of the scaled matrix, which replaces smaller pivots, such that the evaluation stays finite.
+const PIVOT_MIN: f64 = 1e-150;
+
+/// Absolute tolerance of the eigenvalues of the scaled matrix, below which the relative tolerance is not applied.
+const ABSOLUTE_TOLERANCE: f64 = 1e-100;
+
+/// Maximal number of Laguerre and bisection steps for one eigenvalue, after which the iteration stops at the current approximation.
+const MAX_ITERATIONS: usize = 100;
+
+/// Number of inverse iteration steps for each eigenvector.
+const INVERSE_ITERATIONS: usize = 2;
+
+/// Relative distance of eigenvalues, below which their eigenvectors are orthogonalized against each other.
+const CLUSTER_TOLERANCE: f64 = 1e-6;
+
+/// Group of indices that spans explicitly known eigenvectors.
+struct Group {
+    /// Eigenvalue of the eigenvectors of the group
+    value: f64,
+    /// Number of consecutive indices in `indices` that belong to each member of the group
+    member_size: usize,
+    /// Indices of all members of the group
+    indices: Vec<usize>,
+}
+
+/// Shifted matrices of an `UltrametricTree` scaled by its maximal absolute row sum, such that all eigenvalues lie in `[-1, 1]`.
+pub(crate) struct Spectrum<'a> {
+    /// Tree of the matrix
+    tree: &'a UltrametricTree,
+    /// Parent of each vertex, where the root is its own parent
+    parents: Vec<usize>,
+    /// Scaled weight of each vertex
+    weights: Vec<f64>,
+    /// Inverse of the scaled weight of each vertex, which is zero for vertices with weight zero
+    inverse_weights: Vec<f64>,
+    /// Number of equal siblings that each vertex represents, which is zero for the represented siblings
+    multiplicities: Vec<usize>,
+    /// Vertex that represents each vertex
+    representatives: Vec<usize>,
+    /// Vertex that identifies the group of the leaves of each vertex
+    leaf_groups: Vec<usize>,
+    /// Number of leaves in the group identified by each vertex
+    group_sizes: Vec<f64>,
+    /// Whether the leaves of each vertex are the first leaves of their group, which contribute to the reduced characteristic polynomial
+    counted_leaves: Vec<bool>,
+    /// Groups of indices with explicitly known eigenvectors
+    groups: Vec<Group>,
+    /// Number of represented vertices with a positive weight
+    positive_weights: usize,
+    /// Number of groups of leaves of represented vertices, which is the degree of the reduced characteristic polynomial
+    degree: usize,
+    /// Maximal absolute row sum of the matrix, by which the matrix is scaled
+    scale: f64,
+}
+
+impl<'a> Spectrum<'a> {
+    /// Prepare the shifted matrices of `tree`.
+    ///
+    /// The absolute row sum of the leaves of a vertex is the absolute level of the vertex times the size of its `partition` plus the absolute levels of its ancestors times the sizes of the `partition` of the ancestors without the `partition` of their child on the path, which is accumulated top-down. Vertices with weight zero are contracted into their parent to find the groups of indices.
+    pub(crate) fn new(tree: &'a UltrametricTree) -> Self {
+        let vertices = &tree.vertices;
+        let num_vertices = vertices.len();
+        let partition_size =
+            |id: usize| return vertices[id].partition_end - vertices[id].partition_start;
+        let mut parents: Vec<usize> = vec![0; num_vertices];
+        let mut weights: Vec<f64> = vertices.iter().map(|vertex| vertex.level).collect();
+        let mut contracted: Vec<usize> = (0..num_vertices).collect();
+        let mut row_sums: Vec<f64> = vec![0.; num_vertices];
+        let mut scale: f64 = 0.;
+        for id in 0..num_vertices {
+            let level = vertices[id].level;
+            scale = f64::max(
+                scale,
+                row_sums[id] + level.abs() * partition_size(id) as f64,
+            );
+            let mut child = id + 1;
+            while child < vertices[id].subtree_end {
+                parents[child] = id;
+                weights[child] -= level;
+                if weights[child] == 0.0 {
+                    contracted[child] = contracted[id];
+                }
+                row_sums[child] = row_sums[id]
+                    + level.abs() * (partition_size(id) - partition_size(child)) as f64;
+                child = vertices[child].subtree_end;
+            }
+        }
+
+        let mut contracted_children: Vec<Vec<usize>> = vec![Vec::new(); num_vertices];
+        let mut contracted_leaves: Vec<Vec<usize>> = vec![Vec::new(); num_vertices];
+        for id in 0..num_vertices {
+            if id > 0 && contracted[id] == id {
+                contracted_children[contracted[parents[id]]].push(id);
+            }
+            let vertex = &vertices[id];
+            contracted_leaves[contracted[id]]
+                .extend_from_slice(&tree.permutation[vertex.leaf_start..vertex.partition_end]);
+        }
+        let mut multiplicities: Vec<usize> = vec![1; num_vertices];
+        let mut representatives: Vec<usize> = (0..num_vertices).collect();
+        let mut groups: Vec<Group> = Vec::new();
+        for id in 0..num_vertices {
+            let mut childless: Vec<usize> = contracted_children[id]
+                .iter()
+                .copied()
+                .filter(|&child| contracted_children[child].is_empty())
+                .collect();
+            childless.sort_by(|&a, &b| {
+                return partition_size(a)
+                    .cmp(&partition_size(b))
+                    .then(vertices[a].level.total_cmp(&vertices[b].level));
+            });
+            let mut start = 0;
+            while start < childless.len() {
+                let representative = childless[start];
+                let member_size = partition_size(representative);
+                let mut end = start + 1;
+                while end < childless.len()
+                    && vertices[childless[end]].level == vertices[representative].level
+                    && partition_size(childless[end]) == member_size
+                {
+                    let member = childless[end];
+                    multiplicities[member..vertices[member].subtree_end].fill(0);
+                    representatives[member] = representative;
+                    end += 1;
+                }
+                multiplicities[representative] = end - start;
+                if end - start > 1 {
+                    let mut indices: Vec<usize> = Vec::with_capacity((end - start) * member_size);
+                    for &member in childless[start..end].iter() {
+                        let member_vertex = &vertices[member];
+                        indices.extend_from_slice(
+                            &tree.permutation
+                                [member_vertex.partition_start..member_vertex.partition_end],
+                        );
+                    }
+                    groups.push(Group {
+                        value: (vertices[representative].level - vertices[id].level)
+                            * member_size as f64,
+                        member_size,
+                        indices,
+                    });
+                }
+                start = end;
+            }
+            if contracted_leaves[id].len() > 1 {
+                groups.push(Group {
+                    value: 0.,
+                    member_size: 1,
+                    indices: std::mem::take(&mut contracted_leaves[id]),
+                });
+            }
+        }
+
+        if scale == 0.0 || !scale.is_finite() {
+            scale = 1.;
+        }
+        let mut leaf_groups: Vec<usize> = vec![0; num_vertices];
+        let mut counted_leaves: Vec<bool> = vec![false; num_vertices];
+        let mut counted_groups: Vec<bool> = vec![false; num_vertices];
+        let mut group_sizes: Vec<f64> = vec![0.; num_vertices];
+        let mut positive_weights = 0;
+        let mut degree = 0;
+        for id in 0..num_vertices {
+            weights[id] /= scale;
+            leaf_groups[id] = representatives[contracted[id]];
+            let vertex = &vertices[id];
+            group_sizes[leaf_groups[id]] += (vertex.partition_end - vertex.leaf_start) as f64;
+            if multiplicities[id] > 0 {
+                positive_weights += (weights[id] > 0.0) as usize;
+                if vertex.leaf_start < vertex.partition_end && !counted_groups[contracted[id]] {
+                    counted_groups[contracted[id]] = true;
+                    counted_leaves[id] = true;
+                    degree += 1;
+                }
+            }
+        }
+        let inverse_weights: Vec<f64> = weights
+            .iter()
+            .map(|&weight| if weight != 0.0 { 1. / weight } else { 0. })
+            .collect();
+        return Spectrum {
+            tree,
+            parents,
+            weights,
+            inverse_weights,
+            multiplicities,
+            representatives,
+            leaf_groups,
+            group_sizes,
+            counted_leaves,
+            groups,
+            positive_weights,
+            degree,
+            scale,
+        };
+    }
+
+    /// Indices, where the vertex `id` is a leaf.
+    fn leaves(&self, id: usize) -> &[usize] {
+        let vertex = &self.tree.vertices[id];
+        return &self.tree.permutation[vertex.leaf_start..vertex.partition_end];
+    }
+
+    /// Indices in the subtree of the vertex `id`.
+    fn partition(&self, id: usize) -> &[usize] {
+        let vertex = &self.tree.vertices[id];
+        return &self.tree.permutation[vertex.partition_start..vertex.partition_end];
+    }
+
+    /// Evaluate the scaled matrix shifted by `x`, which returns the number of roots of the reduced characteristic polynomial smaller than `x` and the sums of `1 / (x - root)` and `1 / (x - root)^2` over all roots.
+    ///
+    /// The sums are the first two derivatives of the logarithm of the reduced characteristic polynomial, which are accumulated from the derivatives of the pivots with respect to `x`. Each represented vertex contributes its multiplicity to its parent, while its pivot only counts once. `sums` is used to accumulate `1^T (D - x * I)^(-1) 1` and its first two derivatives over the children of each vertex.
+    fn evaluate(&self, x: f64, sums: &mut [[f64; 3]]) -> (usize, f64, f64) {
+        let leaf_x = if x.abs() < PIVOT_MIN { PIVOT_MIN } else { x };
+        let inverse_x = 1. / leaf_x;
+        let leaf_negative = (leaf_x > 0.0) as usize;
+        sums.fill([0.; 3]);
+        let mut negative = 0;
+        let mut first = 0.;
+        let mut second = 0.;
+        for id in (0..self.tree.vertices.len()).rev() {
+            let multiplicity = self.multiplicities[id];
+            if multiplicity == 0 {
+                continue;
+            }
+            let [mut g, mut g1, mut g2] = sums[id];
+            let leaf_count = self.leaves(id).len() as f64;
+            if leaf_count > 0.0 {
+                g -= leaf_count * inverse_x;
+                g1 += leaf_count * inverse_x * inverse_x;
+                g2 -= 2. * leaf_count * inverse_x * inverse_x * inverse_x;
+                if self.counted_leaves[id] {
+                    negative += leaf_negative;
+                    first += inverse_x;
+                    second += inverse_x * inverse_x;
+                }
+            }
+            let inverse_weight = self.inverse_weights[id];
+            if inverse_weight != 0.0 {
+                let mut pivot = -inverse_weight - g;
+                if pivot.abs() < PIVOT_MIN {
+                    pivot = -PIVOT_MIN;
+                }
+                let inverse_pivot = 1. / pivot;
+                negative += (pivot < 0.0) as usize;
+                first -= g1 * inverse_pivot;
+                second += (g2 + g1 * g1 * inverse_pivot) * inverse_pivot;
+                let factor = inverse_weight * inverse_weight * inverse_pivot * inverse_pivot;
+                g2 = (g2 + 2. * g1 * g1 * inverse_pivot) * factor;
+                g1 *= factor;
+                g = inverse_weight + inverse_weight * inverse_weight * inverse_pivot;
+            }
+            if id > 0 {
+                let multiplicity = multiplicity as f64;
+                let parent_sums = &mut sums[self.parents[id]];
+                parent_sums[0] += multiplicity * g;
+                parent_sums[1] += multiplicity * g1;
+                parent_sums[2] += multiplicity * g2;
+            }
+        }
+        let count = negative
+            .saturating_sub(self.positive_weights)
+            .min(self.degree);
+        return (count, first, second);
+    }
+
+    /// Solve the equation system of the scaled matrix shifted by `x` in linear time, where the right-hand side `rhs` is constant on the groups of indices.
+    ///
+    /// As in [`inverse`](crate::UltrametricTree::inverse), each vertex is annotated bottom-up in `relations` with the normalized linear relation `a * sum + c * prev_sum = d`, where `sum` is the sum of the solution over the `partition` of the vertex and `prev_sum` is the partial product of all ancestors of the vertex. The leaves of a vertex are combined into one member with `a = -x`, and the represented siblings into one member. The member with the smallest `|a|` is the pivot of the vertex, which is stored in `pivots` as `None` for the leaves, such that only ratios of at most one are accumulated. Vertices without members, whose children are all represented by other vertices, have the relation `sum = 0`. Then, the sums and partial products are propagated top-down in `sums` and `prev_sums`, where the sum of the pivot is the remainder of the sum of the vertex. Finally, the solution is averaged over each group of leaves, which removes the components of the explicitly known eigenvectors that arise from rounding errors at singular shifts.
+    #[allow(clippy::too_many_arguments)]
+    fn solve(
+        &self,
+        x: f64,
+        rhs: &[f64],
+        solution: &mut [f64],
+        relations: &mut [[f64; 3]],
+        pivots: &mut [Option<usize>],
+        sums: &mut [f64],
+        prev_sums: &mut [f64],
+    ) {
+        let num_vertices = self.tree.vertices.len();
+        for id in (0..num_vertices).rev() {
+            if self.multiplicities[id] == 0 {
+                continue;
+            }
+            let leaves = self.leaves(id);
+            let leaf_count = leaves.len() as f64;
+            let leaf_rhs = leaves.first().map_or(0., |&idx| rhs[idx]);
+            let mut pivot = None;
+            let mut pivot_a = if leaves.is_empty() { f64::INFINITY } else { -x };
+            for child in self.children(id) {
+                if relations[child][0].abs() < pivot_a.abs() {
+                    pivot = Some(child);
+                    pivot_a = relations[child][0];
+                }
+            }
+            if pivot_a.is_infinite() {
+                relations[id] = [1., 0., 0.];
+                pivots[id] = None;
+                continue;
+            }
+            let (mut c, mut d) = match pivot {
+                Some(child) => self.member_relation(child, relations),
+                None => (leaf_count, leaf_count * leaf_rhs),
+            };
+            if pivot.is_some() && !leaves.is_empty() {
+                let ratio = pivot_a / guard(-x);
+                c += ratio * leaf_count;
+                d += ratio * leaf_count * leaf_rhs;
+            }
+            for child in self.children(id) {
+                if Some(child) != pivot {
+                    let ratio = pivot_a / guard(relations[child][0]);
+                    let (child_c, child_d) = self.member_relation(child, relations);
+                    c += ratio * child_c;
+                    d += ratio * child_d;
+                }
+            }
+            let a = pivot_a + c * self.weights[id];
+            let mut norm = f64::max(a.abs(), c.abs());
+            if norm == 0.0 || !norm.is_finite() {
+                norm = 1.;
+            }
+            relations[id] = [a / norm, c / norm, d / norm];
+            pivots[id] = pivot;
+        }
+
+        if num_vertices > 0 {
+            sums[0] = relations[0][2] / guard(relations[0][0]);
+            prev_sums[0] = 0.;
+        }
+        for id in 0..num_vertices {
+            if self.multiplicities[id] == 0 {
+                continue;
+            }
+            let prev_sum = prev_sums[id] + self.weights[id] * sums[id];
+            let mut remainder = sums[id];
+            let leaves = self.leaves(id);
+            if pivots[id].is_some() && !leaves.is_empty() {
+                for &idx in leaves.iter() {
+                    solution[idx] = (rhs[idx] - prev_sum) / guard(-x);
+                    remainder -= solution[idx];
+                }
+            }
+            for child in self.children(id) {
+                if Some(child) != pivots[id] {
+                    let [child_a, child_c, child_d] = relations[child];
+                    sums[child] = (child_d - child_c * prev_sum) / guard(child_a);
+                    prev_sums[child] = prev_sum;
+                    remainder -= self.multiplicities[child] as f64 * sums[child];
+                }
+            }
+            match pivots[id] {
+                Some(child) => {
+                    sums[child] = remainder / self.multiplicities[child] as f64;
+                    prev_sums[child] = prev_sum;
+                }
+                None => {
+                    for &idx in leaves.iter() {
+                        solution[idx] = remainder / leaves.len() as f64;
+                    }
+                }
+            }
+        }
+        for id in 0..num_vertices {
+            let representative = self.representatives[id];
+            if representative != id {
+                for (&idx, &representative_idx) in self
+                    .partition(id)
+                    .iter()
+                    .zip(self.partition(representative))
+                {
+                    solution[idx] = solution[representative_idx];
+                }
+            }
+        }
+        sums.fill(0.);
+        for id in 0..num_vertices {
+            for &idx in self.leaves(id).iter() {
+                sums[self.leaf_groups[id]] += solution[idx];
+            }
+        }
+        for id in 0..num_vertices {
+            let group = self.leaf_groups[id];
+            for &idx in self.leaves(id).iter() {
+                solution[idx] = sums[group] / self.group_sizes[group];
+            }
+        }
+    }
+
+    /// Iterator over the represented children of the vertex `id`.
+    fn children(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        let subtree_end = self.tree.vertices[id].subtree_end;
+        let first_child = if id + 1 < subtree_end {
+            Some(id + 1)
+        } else {
+            None
+        };
+        return std::iter::successors(first_child, move |&child| {
+            let next_child = self.tree.vertices[child].subtree_end;
+            if next_child < subtree_end {
+                Some(next_child)
+            } else {
+                None
+            }
+        })
+        .filter(move |&child| self.multiplicities[child] > 0);
+    }
+
+    /// Coefficients `c` and `d` of the relation of the represented siblings of the vertex `id`, which is used in [`solve`](Spectrum::solve).
+    fn member_relation(&self, id: usize, relations: &[[f64; 3]]) -> (f64, f64) {
+        let multiplicity = self.multiplicities[id] as f64;
+        return (
+            multiplicity * relations[id][1],
+            multiplicity * relations[id][2],
+        );
+    }
+
+    /// Calculate the roots of the reduced characteristic polynomial of the scaled matrix in ascending order.
+    ///
+    /// The roots are separated by splitting intervals, whose bounds have known counts of smaller roots. From the midpoint of an interval, Laguerre's method is run towards the next smaller and the next larger root. Since all roots are real, the iterates do not pass the nearest root in their direction and converge cubically to simple roots, while the counts safeguard the iteration against rounding errors with bisection steps. All roots between a found root and the bound of its bracket are equal up to the tolerance. Thus, each root costs a bounded number of evaluations in linear time.
+    fn reduced_eigenvalues(&self, sums: &mut [[f64; 3]]) -> Vec<f64> {
+        let mut values: Vec<f64> = vec![0.; self.degree];
+        let mut intervals: Vec<(f64, f64, usize, usize)> = vec![(-2., 2., 0, self.degree)];
+        while let Some((lower, upper, start, end)) = intervals.pop() {
+            if start >= end {
+                continue;
+            }
+            let x = 0.5 * (lower + upper);
+            if upper - lower <= tolerance(x) {
+                values[start..end].fill(x);
+                continue;
+            }
+            let (count, first, second) = self.evaluate(x, sums);
+            let count = count.clamp(start, end);
+            let derivatives = (first, second);
+            if count > start {
+                let (root, below, below_count) =
+                    self.find_root(x, derivatives, (lower, start), count, -1., sums);
+                values[below_count..count].fill(root);
+                intervals.push((lower, below, start, below_count));
+            }
+            if count < end {
+                let (root, above, above_count) =
+                    self.find_root(x, derivatives, (upper, end), count, 1., sums);
+                values[count..above_count].fill(root);
+                intervals.push((above, upper, above_count, end));
+            }
+        }
+        return values;
+    }
+
+    /// Find the nearest root from `x` in the direction `direction`, where `count` roots are smaller than `x`, `derivatives` are the logarithmic derivatives at `x` and `bound` is a point beyond the root with its count.
+    ///
+    /// If the steps do not shrink fast, which indicates a multiple root, the multiplicity is estimated by `first^2 / second` and used in the step. A step is replaced by a bisection step towards the bound, if it is not finite, does not shrink or leaves the interval. A step below the tolerance is only accepted, if the count changes at twice the tolerance beyond the iterate. Points that pass the root become the new bound. Returns the root and the final bound with its count, where all roots between the root and the bound are equal up to the tolerance.
+    fn find_root(
+        &self,
+        x: f64,
+        derivatives: (f64, f64),
+        bound: (f64, usize),
+        count: usize,
+        direction: f64,
+        sums: &mut [[f64; 3]],
+    ) -> (f64, f64, usize) {
+        let degree = self.degree as f64;
+        let (mut x, (mut first, mut second), (mut bound, mut bound_count)) =
+            (x, derivatives, bound);
+        let mut previous_step = f64::INFINITY;
+        for _ in 0..MAX_ITERATIONS {
+            if (bound - x).abs() <= tolerance(x) {
+                break;
+            }
+            let laguerre_step = |multiplicity: f64| {
+                let root_term = ((degree - multiplicity) / multiplicity
+                    * (degree * second - first * first))
+                    .max(0.)
+                    .sqrt();
+                return degree / (root_term - direction * first);
+            };
+            let mut step = laguerre_step(1.);
+            if step > 0.1 * previous_step {
+                let multiplicity = (first * first / second).round().clamp(1., degree);
+                step = laguerre_step(multiplicity);
+            }
+            let mut next = x + direction * step;
+            if !(first.is_finite()
+                && second.is_finite()
+                && step > 0.0
+                && step < previous_step
+                && direction * (bound - next) > 0.0)
+            {
+                next = 0.5 * (x + bound);
+                step = f64::INFINITY;
+            } else if step <= tolerance(x) {
+                next = x + direction * 2. * tolerance(x);
+                if direction * (bound - next) <= 0.0 {
+                    break;
+                }
+                step = 0.;
+            }
+            previous_step = step;
+            let (next_count, next_first, next_second) = self.evaluate(next, sums);
+            let passed = if direction < 0.0 {
+                next_count < count
+            } else {
+                next_count > count
+            };
+            if passed {
+                bound = next;
+                bound_count = next_count;
+            } else {
+                x = next;
+                first = next_first;
+                second = next_second;
+            }
+        }
+        return (x, bound, bound_count);
+    }
+
+    /// Calculate all eigenvalues in ascending order.
+    pub(crate) fn eigenvalues(&self) -> DVector<f64> {
+        let mut sums: Vec<[f64; 3]> = vec![[0.; 3]; self.tree.vertices.len()];
+        let mut eigenvalues: Vec<f64> = self
+            .reduced_eigenvalues(&mut sums)
+            .iter()
+            .map(|value| value * self.scale)
+            .collect();
+        for group in self.groups.iter() {
+            let num_members = group.indices.len() / group.member_size;
+            eigenvalues.extend(std::iter::repeat_n(group.value, num_members - 1));
+        }
+        eigenvalues.sort_by(|a, b| a.total_cmp(b));
+        return DVector::from_vec(eigenvalues);
+    }
+
+    /// Calculate the eigendecomposition with eigenvalues in ascending order.
+    ///
+    /// The eigenvectors of a group with the members `m_0, ..., m_(k-1)` are the normalized vectors `1_(m_0) + ... + 1_(m_(j-1)) - j * 1_(m_j)`, where `1_m` is the indicator vector of a member. The eigenvectors of the roots of the reduced characteristic polynomial are calculated by inverse iteration in linear time from pseudo-random vectors that are constant on the groups. Eigenvectors of roots, whose relative distance is below `CLUSTER_TOLERANCE`, are orthogonalized against each other, which costs linear time for each pair of such roots.
+    pub(crate) fn eigen(&self) -> SymmetricEigen<f64, Dynamic> {
+        let size = self.tree.permutation.len();
+        let num_vertices = self.tree.vertices.len();
+        let mut sums: Vec<[f64; 3]> = vec![[0.; 3]; num_vertices];
+        let values = self.reduced_eigenvalues(&mut sums);
+        let mut columns: Vec<(f64, Option<usize>, usize)> = Vec::with_capacity(size);
+        for (k, &value) in values.iter().enumerate() {
+            columns.push((value * self.scale, None, k));
+        }
+        for (group_idx, group) in self.groups.iter().enumerate() {
+            for j in 1..group.indices.len() / group.member_size {
+                columns.push((group.value, Some(group_idx), j));
+            }
+        }
+        columns.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut eigenvalues = DVector::<f64>::zeros(size);
+        let mut eigenvectors = DMatrix::<f64>::zeros(size, size);
+        let mut positions: Vec<usize> = vec![0; values.len()];
+        for (position, &(value, group_idx, j)) in columns.iter().enumerate() {
+            eigenvalues[position] = value;
+            match group_idx {
+                Some(group_idx) => {
+                    let group = &self.groups[group_idx];
+                    let norm = ((j * (j + 1) * group.member_size) as f64).sqrt();
+                    let member_size = group.member_size;
+                    for &idx in group.indices[..j * member_size].iter() {
+                        eigenvectors[(idx, position)] = 1. / norm;
+                    }
+                    for &idx in group.indices[j * member_size..(j + 1) * member_size].iter() {
+                        eigenvectors[(idx, position)] = -(j as f64) / norm;
+                    }
+                }
+                None => positions[j] = position,
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut rhs: Vec<f64> = vec![0.; size];
+        let mut solution: Vec<f64> = vec![0.; size];
+        let mut entries: Vec<f64> = vec![0.; num_vertices];
+        let mut relations: Vec<[f64; 3]> = vec![[0.; 3]; num_vertices];
+        let mut pivots: Vec<Option<usize>> = vec![None; num_vertices];
+        let mut vertex_sums: Vec<f64> = vec![0.; num_vertices];
+        let mut prev_sums: Vec<f64> = vec![0.; num_vertices];
+        let mut cluster_start = 0;
+        for (k, &value) in values.iter().enumerate() {
+            if k == 0
+                || value - values[k - 1]
+                    > CLUSTER_TOLERANCE * f64::max(value.abs(), values[k - 1].abs())
+            {
+                cluster_start = k;
+            }
+            for entry in entries.iter_mut() {
+                *entry = rng.gen_range(-1.0..1.0);
+            }
+            for id in 0..num_vertices {
+                let entry = entries[self.leaf_groups[id]];
+                for &idx in self.leaves(id).iter() {
+                    rhs[idx] = entry;
+                }
+            }
+            for _ in 0..INVERSE_ITERATIONS {
+                self.solve(
+                    value,
+                    &rhs,
+                    &mut solution,
+                    &mut relations,
+                    &mut pivots,
+                    &mut vertex_sums,
+                    &mut prev_sums,
+                );
+                let mut vector = DVector::from_column_slice(&solution);
+                let max_entry = vector.amax();
+                if max_entry != 0.0 && max_entry.is_finite() {
+                    vector /= max_entry;
+                }
+                for _ in 0..2 {
+                    for &position in &positions[cluster_start..k] {
+                        let column = eigenvectors.column(position);
+                        let product = column.dot(&vector);
+                        vector.axpy(-product, &column, 1.);
+                    }
+                }
+                vector.normalize_mut();
+                rhs.copy_from_slice(vector.as_slice());
+            }
+            eigenvectors.column_mut(positions[k]).copy_from_slice(&rhs);
+        }
+        return SymmetricEigen {
+            eigenvectors,
+            eigenvalues,
+        };
+    }
+}
+
+/// Replace a coefficient that is too small to divide by with `PIVOT_MIN` of the same sign.
+fn guard(a: f64) -> f64 {
+    if a.abs() < PIVOT_MIN {
+        return PIVOT_MIN.copysign(a);
+    }
+    return a;
+}
+
+/// Tolerance of an eigenvalue of the scaled matrix close to `x`.
+fn tolerance(x: f64) -> f64 {
+    return 2. * f64::EPSILON * x.abs() + ABSOLUTE_TOLERANCE;
+}
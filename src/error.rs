@@ -0,0 +1,144 @@
+//! Errors returned by the functions of this crate.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::PyErr;
+use std::fmt;
+
+/// Error that occurs when an operation on an ultrametric matrix or an `UltrametricTree` fails.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UltrametricError {
+    /// The ultrametric matrix is singular, e.g. in [`solve`](crate::UltrametricTree::solve).
+    SingularMatrix,
+    /// The size of a vector or matrix does not match the size of the ultrametric matrix.
+    DimensionMismatch {
+        /// Size of the ultrametric matrix
+        expected: usize,
+        /// Size of the given vector or matrix
+        found: usize,
+    },
+    /// The matrix has no rows or no columns.
+    EmptyMatrix,
+    /// The matrix is not square.
+    NonSquareMatrix {
+        /// Number of rows of the matrix
+        rows: usize,
+        /// Number of columns of the matrix
+        columns: usize,
+    },
+    /// The element of the matrix at `(row, column)` is NaN.
+    NotANumber {
+        /// Row index of the element
+        row: usize,
+        /// Column index of the element
+        column: usize,
+    },
+    /// The elements of the matrix at `(row, column)` and `(column, row)` differ.
+    NonSymmetric {
+        /// Row index of the element
+        row: usize,
+        /// Column index of the element
+        column: usize,
+    },
+    /// The off-diagonal element of the matrix at `(row, column)` is negative.
+    NegativeElement {
+        /// Row index of the element
+        row: usize,
+        /// Column index of the element
+        column: usize,
+    },
+    /// The indices violate the ultrametric inequality `a[(i, j)] >= min(a[(i, k)], a[(k, j)])`.
+    NonUltrametric {
+        /// First index of the violated inequality
+        i: usize,
+        /// Second index of the violated inequality
+        j: usize,
+        /// Index of the intermediate element of the violated inequality
+        k: usize,
+    },
+    /// The row `row` of a linkage matrix is invalid, e.g. it references an unknown or already merged cluster or its distance is smaller than the distances of the merged clusters.
+    InvalidLinkage {
+        /// Index of the invalid row
+        row: usize,
+    },
+    /// The Newick string is malformed at the byte position `position`.
+    InvalidNewick {
+        /// Byte position of the error in the Newick string
+        position: usize,
+        /// Description of the error
+        reason: &'static str,
+    },
+    /// The data in the binary format of an `UltrametricTree` is malformed, see [`binary`](crate::binary).
+    InvalidBinary {
+        /// Description of the error
+        reason: &'static str,
+    },
+    /// The stored representation of an `UltrametricTree` violates an invariant of the tree.
+    InvalidTree {
+        /// Description of the violated invariant
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for UltrametricError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UltrametricError::SingularMatrix => write!(f, "the ultrametric matrix is singular"),
+            UltrametricError::DimensionMismatch { expected, found } => write!(
+                f,
+                "dimension mismatch: expected size {}, found size {}",
+                expected, found
+            ),
+            UltrametricError::EmptyMatrix => write!(f, "the matrix is empty"),
+            UltrametricError::NonSquareMatrix { rows, columns } => write!(
+                f,
+                "the matrix is not square: found {} rows and {} columns",
+                rows, columns
+            ),
+            UltrametricError::NotANumber { row, column } => write!(
+                f,
+                "the matrix contains NaN at row {}, column {}",
+                row, column
+            ),
+            UltrametricError::NonSymmetric { row, column } => write!(
+                f,
+                "the matrix is not symmetric at row {}, column {}",
+                row, column
+            ),
+            UltrametricError::NegativeElement { row, column } => write!(
+                f,
+                "the matrix contains a negative element at row {}, column {}",
+                row, column
+            ),
+            UltrametricError::NonUltrametric { i, j, k } => write!(
+                f,
+                "the matrix is not ultrametric: a[{i}, {j}] < min(a[{i}, {k}], a[{k}, {j}])",
+                i = i,
+                j = j,
+                k = k
+            ),
+            UltrametricError::InvalidLinkage { row } => {
+                write!(f, "the linkage matrix is invalid at row {}", row)
+            }
+            UltrametricError::InvalidNewick { position, reason } => write!(
+                f,
+                "the Newick string is invalid at position {}: {}",
+                position, reason
+            ),
+            UltrametricError::InvalidBinary { reason } => {
+                write!(f, "the binary data is invalid: {}", reason)
+            }
+            UltrametricError::InvalidTree { reason } => {
+                write!(f, "the ultrametric tree is invalid: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UltrametricError {}
+
+/// Conversion of an `UltrametricError` into a Python `ValueError`.
+impl From<UltrametricError> for PyErr {
+    fn from(error: UltrametricError) -> PyErr {
+        PyValueError::new_err(error.to_string())
+    }
+}
@@ -0,0 +1,54 @@
+//! Index for the lowest common ancestor of two vertices of an `UltrametricTree`.
+
+use crate::ultrametric_tree::Vertex;
+
+/// Heavy path decomposition of the vertices of an `UltrametricTree`.
+///
+/// The heavy child of a vertex is its child with the largest subtree, which continues the path of the vertex, while the other children start new paths. Each path from a vertex to the root crosses at most `log2(m)` paths, where `m` is the number of vertices. Thus, the lowest common ancestor is found in logarithmic time using linear memory.
+#[derive(Default, Clone)]
+pub(crate) struct LcaIndex {
+    /// Vector storing for each vertex the first vertex of its heavy path
+    heads: Vec<usize>,
+    /// Vector storing the parent of each vertex, where the root is its own parent
+    parents: Vec<usize>,
+}
+
+impl LcaIndex {
+    /// Construct the index of the vertices of a tree in pre-order.
+    pub(crate) fn new(vertices: &[Vertex]) -> Self {
+        let mut heads: Vec<usize> = (0..vertices.len()).collect();
+        let mut parents: Vec<usize> = vec![0; vertices.len()];
+        for id in 0..vertices.len() {
+            let subtree_end = vertices[id].subtree_end;
+            let mut heavy_child = id;
+            let mut heavy_size = 0;
+            let mut child = id + 1;
+            while child < subtree_end {
+                parents[child] = id;
+                let child_size = vertices[child].subtree_end - child;
+                if child_size > heavy_size {
+                    heavy_child = child;
+                    heavy_size = child_size;
+                }
+                child = vertices[child].subtree_end;
+            }
+            if heavy_child != id {
+                heads[heavy_child] = heads[id];
+            }
+        }
+        return LcaIndex { heads, parents };
+    }
+
+    /// Lowest common ancestor of the vertices `u` and `v`.
+    ///
+    /// The vertex with the later head in pre-order moves to the parent of its head. This head cannot be an ancestor of the other vertex, since it would be on the same path as the other head. Once both vertices are on the same path, the vertex that comes first in pre-order is the lowest common ancestor.
+    pub(crate) fn lca(&self, mut u: usize, mut v: usize) -> usize {
+        while self.heads[u] != self.heads[v] {
+            if self.heads[u] < self.heads[v] {
+                std::mem::swap(&mut u, &mut v);
+            }
+            u = self.parents[self.heads[u]];
+        }
+        return usize::min(u, v);
+    }
+}
@@ -3,13 +3,32 @@
 //! The [`UltrametricTree`](ultrametric_tree::UltrametricTree) is a data structure that represents the structure of an ultrametric matrix. This tree can then be used to efficiently implement algorithms, e.g. multiplication of ultramettric matrix and vector.
 //!
 //! The [`utils`](utils) module provides functions to generate random ultrametric matrices and check if a matrix is ultrametric.
+//!
+//! The [`binary`](binary) module provides a compact binary format of the `UltrametricTree`, which is loaded through a memory map without deserialization.
+//!
+//! With the `serde` feature, the `UltrametricTree` implements `Serialize` and `Deserialize`, see the `serialization` module.
+//!
+//! With the `rayon` feature, the construction of the `UltrametricTree` from a matrix and the multiplication with a vector are parallelized over independent subtrees. The results do not depend on the number of threads.
+
+#![allow(clippy::needless_return)]
 
 use pyo3::prelude::*;
 use pyo3::wrap_pymodule;
 
+pub mod binary;
+mod eigen;
+pub mod error;
+mod lca;
+mod newick;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod ultrametric_inverse;
 pub mod ultrametric_tree;
 pub mod utils;
 
+pub use self::binary::MappedUltrametricTree;
+pub use self::error::UltrametricError;
+pub use self::ultrametric_inverse::UltrametricInverse;
 pub use self::ultrametric_tree::UltrametricTree;
 use crate::utils::PyInit_utils;
 pub extern crate nalgebra as na;
@@ -17,6 +36,8 @@ pub extern crate nalgebra as na;
 #[pymodule]
 fn ultrametric_matrix_tools(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<UltrametricTree>()?;
+    m.add_class::<UltrametricInverse>()?;
+    m.add_class::<MappedUltrametricTree>()?;
     m.add_wrapped(wrap_pymodule!(utils))?;
     Ok(())
 }
@@ -0,0 +1,237 @@
+//! Parser for trees in the Newick format.
+
+use crate::error::UltrametricError;
+use std::collections::HashSet;
+
+/// Node of a tree that is parsed from a Newick string.
+pub(crate) struct NewickNode {
+    /// Position of the parent in the vector of nodes
+    pub(crate) parent: Option<usize>,
+    /// Length of the branch from the parent to the node
+    pub(crate) length: f64,
+    /// Index of the matrix for leaves in the order of their appearance and `None` for internal nodes
+    pub(crate) leaf_idx: Option<usize>,
+}
+
+/// Characters that terminate an unquoted label or a branch length.
+const DELIMITERS: &[u8] = b"(),:;[]'";
+
+/// Cursor into a Newick string that reads its tokens.
+struct NewickReader<'a> {
+    /// Newick string that is parsed
+    newick: &'a str,
+    /// Byte position of the next token
+    position: usize,
+}
+
+impl<'a> NewickReader<'a> {
+    /// Construct the error at the current position.
+    fn error(&self, reason: &'static str) -> UltrametricError {
+        return UltrametricError::InvalidNewick {
+            position: self.position,
+            reason,
+        };
+    }
+
+    /// Skip whitespace and comments in square brackets and return the next character.
+    fn peek(&mut self) -> Result<Option<u8>, UltrametricError> {
+        let bytes = self.newick.as_bytes();
+        while self.position < bytes.len() {
+            if bytes[self.position].is_ascii_whitespace() {
+                self.position += 1;
+            } else if bytes[self.position] == b'[' {
+                match bytes[self.position..].iter().position(|&byte| byte == b']') {
+                    Some(length) => self.position += length + 1,
+                    None => return Err(self.error("unterminated comment")),
+                }
+            } else {
+                return Ok(Some(bytes[self.position]));
+            }
+        }
+        return Ok(None);
+    }
+
+    /// Read an unquoted token up to the next delimiter or whitespace.
+    fn read_token(&mut self) -> &'a str {
+        let bytes = self.newick.as_bytes();
+        let start = self.position;
+        while self.position < bytes.len()
+            && !DELIMITERS.contains(&bytes[self.position])
+            && !bytes[self.position].is_ascii_whitespace()
+        {
+            self.position += 1;
+        }
+        return &self.newick[start..self.position];
+    }
+
+    /// Read an optional label, which is either unquoted or enclosed in single quotes, where `''` denotes a quote.
+    fn read_label(&mut self) -> Result<String, UltrametricError> {
+        if self.peek()? != Some(b'\'') {
+            return Ok(self.read_token().to_string());
+        }
+        let start = self.position;
+        let bytes = self.newick.as_bytes();
+        let mut label = String::new();
+        self.position += 1;
+        loop {
+            match bytes[self.position..]
+                .iter()
+                .position(|&byte| byte == b'\'')
+            {
+                Some(length) => {
+                    label.push_str(&self.newick[self.position..self.position + length]);
+                    self.position += length + 1;
+                    if bytes.get(self.position) != Some(&b'\'') {
+                        return Ok(label);
+                    }
+                    label.push('\'');
+                    self.position += 1;
+                }
+                None => {
+                    self.position = start;
+                    return Err(self.error("unterminated quoted label"));
+                }
+            }
+        }
+    }
+
+    /// Read an optional branch length, which defaults to zero and has to be finite.
+    ///
+    /// Negative branch lengths are allowed, since the level of a vertex of an `UltrametricTree` can be smaller than the level of its parent.
+    fn read_length(&mut self) -> Result<f64, UltrametricError> {
+        if self.peek()? != Some(b':') {
+            return Ok(0.0);
+        }
+        self.position += 1;
+        self.peek()?;
+        let start = self.position;
+        match self.read_token().parse::<f64>() {
+            Ok(length) if length.is_finite() => return Ok(length),
+            _ => {
+                self.position = start;
+                return Err(self.error("invalid branch length"));
+            }
+        }
+    }
+}
+
+/// Format a label for a Newick string, where labels that are empty or contain delimiters or whitespace are enclosed in single quotes.
+pub(crate) fn quote_label(label: &str) -> String {
+    let is_plain = !label.is_empty()
+        && label
+            .bytes()
+            .all(|byte| !DELIMITERS.contains(&byte) && !byte.is_ascii_whitespace());
+    if is_plain {
+        return label.to_string();
+    }
+    return format!("'{}'", label.replace('\'', "''"));
+}
+
+/// Parse a Newick string into its nodes in pre-order, such that the root is the first node, and the labels of the leaves.
+///
+/// The leaves are indexed in the order of their appearance, where the labels of the leaves have to be unique. Labels of internal nodes are ignored.
+pub(crate) fn parse_newick(
+    newick: &str,
+) -> Result<(Vec<NewickNode>, Vec<String>), UltrametricError> {
+    let mut reader = NewickReader {
+        newick,
+        position: 0,
+    };
+    let mut nodes: Vec<NewickNode> = Vec::new();
+    let mut labels: Vec<String> = Vec::new();
+    let mut seen_labels: HashSet<String> = HashSet::new();
+    let mut stack: Vec<usize> = Vec::new();
+    'subtree: loop {
+        while reader.peek()? == Some(b'(') {
+            let parent = stack.last().copied();
+            stack.push(nodes.len());
+            nodes.push(NewickNode {
+                parent,
+                length: 0.0,
+                leaf_idx: None,
+            });
+            reader.position += 1;
+        }
+        reader.peek()?;
+        let label_position = reader.position;
+        let label = reader.read_label()?;
+        if label.is_empty() {
+            return Err(reader.error("expected a leaf label"));
+        }
+        if !seen_labels.insert(label.clone()) {
+            reader.position = label_position;
+            return Err(reader.error("duplicate leaf label"));
+        }
+        let mut node_id = nodes.len();
+        nodes.push(NewickNode {
+            parent: stack.last().copied(),
+            length: 0.0,
+            leaf_idx: Some(labels.len()),
+        });
+        labels.push(label);
+        loop {
+            nodes[node_id].length = reader.read_length()?;
+            match reader.peek()? {
+                Some(b',') if !stack.is_empty() => {
+                    reader.position += 1;
+                    continue 'subtree;
+                }
+                Some(b')') if !stack.is_empty() => {
+                    reader.position += 1;
+                    node_id = stack.pop().unwrap();
+                    reader.read_label()?;
+                }
+                Some(b';') if stack.is_empty() => {
+                    reader.position += 1;
+                    if reader.peek()?.is_some() {
+                        return Err(reader.error("unexpected characters after ';'"));
+                    }
+                    break 'subtree;
+                }
+                Some(b',') | Some(b')') => return Err(reader.error("unbalanced parentheses")),
+                Some(b';') | None if !stack.is_empty() => return Err(reader.error("expected ')'")),
+                Some(_) => return Err(reader.error("unexpected character")),
+                None => return Err(reader.error("expected ';'")),
+            }
+        }
+    }
+
+    return Ok((nodes, labels));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors() {
+        let invalid_newicks: [(&str, &str); 6] = [
+            ("(A:1,B:x):1;", "invalid branch length"),
+            ("(A:1,B:inf):1;", "invalid branch length"),
+            ("(A:1,A:1):1;", "duplicate leaf label"),
+            ("(A:1,:1):1;", "expected a leaf label"),
+            ("(A:1,B:1;", "expected ')'"),
+            ("(A:1,B:1):1", "expected ';'"),
+        ];
+        for &(newick, expected_reason) in invalid_newicks.iter() {
+            match parse_newick(newick) {
+                Err(UltrametricError::InvalidNewick { reason, .. }) => {
+                    assert_eq!(reason, expected_reason)
+                }
+                _ => panic!("expected an error for {}", newick),
+            }
+        }
+    }
+
+    #[test]
+    fn quoted_labels() {
+        let (nodes, labels) = parse_newick("('A B':1,'it''s':2):1;").unwrap();
+        assert_eq!(labels, vec!["A B".to_string(), "it's".to_string()]);
+        assert_eq!(
+            nodes.iter().filter(|node| node.leaf_idx.is_some()).count(),
+            2
+        );
+        assert_eq!(quote_label("it's"), "'it''s'");
+        assert_eq!(quote_label("A"), "A");
+    }
+}
@@ -0,0 +1,83 @@
+//! Serialization of the `UltrametricTree` with [`serde`](https://serde.rs), which requires the `serde` feature.
+//!
+//! The tree is stored as a struct with the fields `version`, `vertices` and `permutation`, where `version` is the version of the format. The vertices are stored in pre-order with their level and the ranges of their subtree and `partition`. When a tree is loaded, the version and the invariants of the tree are checked, such that an invalid tree is rejected instead of causing a panic later on.
+
+use crate::ultrametric_tree::{UltrametricTree, Vertex};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Version of the serialization format that is written by [`UltrametricTree::serialize`].
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Stored representation of an [`UltrametricTree`] that is written.
+#[derive(Serialize)]
+#[serde(rename = "UltrametricTree")]
+struct StoredTreeRef<'a> {
+    /// Version of the format
+    version: u32,
+    /// Vertices of the tree in pre-order
+    vertices: &'a [Vertex],
+    /// Permutation of the indices
+    permutation: &'a [usize],
+}
+
+/// Stored representation of an [`UltrametricTree`] that is read.
+#[derive(Deserialize)]
+#[serde(rename = "UltrametricTree")]
+struct StoredTree {
+    /// Version of the format
+    version: u32,
+    /// Vertices of the tree in pre-order
+    vertices: Vec<Vertex>,
+    /// Permutation of the indices
+    permutation: Vec<usize>,
+}
+
+/// Serialization of an `UltrametricTree` with the current [`FORMAT_VERSION`].
+///
+/// # Example:
+///
+/// ```
+/// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+/// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+/// let json = serde_json::to_string(&tree).unwrap();
+/// let loaded: ultrametric_matrix_tools::UltrametricTree = serde_json::from_str(&json).unwrap();
+///
+/// assert_eq!(loaded.to_matrix(), matrix);
+/// ```
+impl Serialize for UltrametricTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return StoredTreeRef {
+            version: FORMAT_VERSION,
+            vertices: &self.vertices,
+            permutation: &self.permutation,
+        }
+        .serialize(serializer);
+    }
+}
+
+/// Deserialization of an `UltrametricTree`, which checks the version of the format and the invariants of the tree.
+///
+/// # Example:
+///
+/// ```
+/// let json = r#"{"version":1,"vertices":[{"level":1.0,"subtree_end":1,
+///     "partition_start":0,"leaf_start":0,"partition_end":2}],"permutation":[1,1]}"#;
+/// let loaded = serde_json::from_str::<ultrametric_matrix_tools::UltrametricTree>(json);
+///
+/// assert!(loaded.is_err());
+/// ```
+impl<'de> Deserialize<'de> for UltrametricTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredTree::deserialize(deserializer)?;
+        if stored.version != FORMAT_VERSION {
+            return Err(D::Error::custom(format!(
+                "unsupported format version {}, expected version {}",
+                stored.version, FORMAT_VERSION
+            )));
+        }
+        return UltrametricTree::from_parts(stored.vertices, stored.permutation)
+            .map_err(D::Error::custom);
+    }
+}
@@ -0,0 +1,332 @@
+//! `UltrametricInverse` implementation.
+
+use crate::error::UltrametricError;
+use crate::ultrametric_tree::vector_from_py;
+use nalgebra::{DMatrix, DVector};
+use ndarray::prelude::*;
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyReadonlyArrayDyn};
+use pyo3::prelude::*;
+
+/// Threshold below which the normalized coefficient `a` of an [`InverseVertex`] is considered to be zero.
+pub(crate) const SINGULAR_TOLERANCE: f64 = 16.0 * f64::EPSILON;
+
+/// Vertex of the eliminated `UltrametricTree` that is stored in an [`UltrametricInverse`].
+///
+/// Each vertex is annotated with the normalized linear relation `a * sum + c * prev_sum = d`, where `sum` is the sum of the solution over the `partition` of the vertex and `prev_sum` is the partial product of all ancestors of the vertex. The coefficients `a` and `c` only depend on the matrix, while `d` depends linearly on the right-hand side.
+#[derive(Default, Clone, Copy)]
+pub(crate) struct InverseVertex {
+    /// Difference of the level of the vertex and the level of its parent
+    pub(crate) weight: f64,
+    /// Coefficient of the sum of the solution over the `partition` of the vertex
+    pub(crate) a: f64,
+    /// Coefficient of the partial product of all ancestors of the vertex
+    pub(crate) c: f64,
+    /// Factor of `d` of the vertex in `d` of its parent
+    pub(crate) d_factor: f64,
+    /// Leaf of the vertex that is the pivot of the vertex
+    pub(crate) pivot_leaf: Option<usize>,
+    /// Factor of the right-hand side at `pivot_leaf` in `d` of the vertex
+    pub(crate) leaf_factor: f64,
+    /// Whether the vertex is the pivot of its parent
+    pub(crate) is_pivot: bool,
+    /// Number of vertices in the subtree of the vertex
+    pub(crate) size: usize,
+}
+
+/// Structured inverse of an ultrametric matrix.
+///
+/// The inverse is not stored explicitly. Instead, the `UltrametricInverse` stores the elimination of the [`UltrametricTree`](crate::UltrametricTree) in pre-order, which is computed in [`inverse`](crate::UltrametricTree::inverse). Thus, the `UltrametricInverse` only needs linear memory and the inverse can be multiplied with a vector in linear time.
+#[pyclass]
+#[derive(Default, Clone)]
+pub struct UltrametricInverse {
+    /// Vertices of the eliminated tree in pre-order
+    pub(crate) vertices: Vec<InverseVertex>,
+    /// Number of rows and columns of the matrix
+    pub(crate) size: usize,
+}
+
+impl UltrametricInverse {
+    /// Calculate the product of the inverse and a vector.
+    ///
+    /// The multiplication is done in two steps. The first step calculates the right-hand sides `d` of the relations bottom-up. The second step resolves the relations top-down to get the product. If the size of `vector` differs from the size of the matrix, then [`UltrametricError::DimensionMismatch`] is returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
+    /// let vector = ultrametric_matrix_tools::na::DVector::from_vec(vec![4.0, 2.0, 7.0, 5.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let inverse = tree.inverse().unwrap();
+    /// let product = inverse.mult(&vector).unwrap();
+    ///
+    /// assert!((&matrix * product - vector).norm() < 1e-12);
+    /// ```
+    pub fn mult(&self, vector: &DVector<f64>) -> Result<DVector<f64>, UltrametricError> {
+        if vector.nrows() != self.size {
+            return Err(UltrametricError::DimensionMismatch {
+                expected: self.size,
+                found: vector.nrows(),
+            });
+        }
+        return Ok(self.mult_unchecked(vector));
+    }
+
+    /// Calculate the product of the inverse and a vector of the size of the matrix as in [`mult`](UltrametricInverse::mult).
+    fn mult_unchecked(&self, vector: &DVector<f64>) -> DVector<f64> {
+        let mut product = DVector::<f64>::zeros(self.size);
+        let num_vertices = self.vertices.len();
+        if num_vertices == 0 {
+            return product;
+        }
+        let mut d = vec![0.; num_vertices];
+        let mut sums = vec![0.; num_vertices];
+        let mut prev_sums = vec![0.; num_vertices];
+        self.mult_subtree(
+            0,
+            vector,
+            &mut d,
+            &mut sums,
+            &mut prev_sums,
+            |leaf_idx, value| {
+                product[leaf_idx] = value;
+            },
+        );
+        return product;
+    }
+
+    /// Calculate the product of the inverse restricted to the subtree of the vertex `root` and `vector`, where the partial product of the ancestors of `root` is zero.
+    ///
+    /// This holds for the root and for the subtrees below vertices with level zero. The buffers `d`, `sums` and `prev_sums` start at the vertex `root` and have at least the size of its subtree. The element of the product at each leaf of the subtree is passed to `set_product`.
+    fn mult_subtree<F>(
+        &self,
+        root: usize,
+        vector: &DVector<f64>,
+        d: &mut [f64],
+        sums: &mut [f64],
+        prev_sums: &mut [f64],
+        mut set_product: F,
+    ) where
+        F: FnMut(usize, f64),
+    {
+        let subtree_end = root + self.vertices[root].size;
+        for id in (root..subtree_end).rev() {
+            let vertex = &self.vertices[id];
+            let mut d_id = 0.;
+            if let Some(leaf_idx) = vertex.pivot_leaf {
+                d_id += vertex.leaf_factor * vector[leaf_idx];
+            }
+            let mut child_id = id + 1;
+            while child_id < id + vertex.size {
+                let child = &self.vertices[child_id];
+                d_id += child.d_factor * d[child_id - root];
+                child_id += child.size;
+            }
+            d[id - root] = d_id;
+        }
+
+        sums[0] = d[0] / self.vertices[root].a;
+        prev_sums[0] = 0.;
+        for id in root..subtree_end {
+            let vertex = &self.vertices[id];
+            let prev_sum = prev_sums[id - root] + vertex.weight * sums[id - root];
+            let mut pivot_id = None;
+            let mut pivot_sum = sums[id - root];
+            let mut child_id = id + 1;
+            while child_id < id + vertex.size {
+                let child = &self.vertices[child_id];
+                prev_sums[child_id - root] = prev_sum;
+                if child.is_pivot {
+                    pivot_id = Some(child_id);
+                } else {
+                    sums[child_id - root] = (d[child_id - root] - child.c * prev_sum) / child.a;
+                    pivot_sum -= sums[child_id - root];
+                }
+                child_id += child.size;
+            }
+            if let Some(pivot_id) = pivot_id {
+                sums[pivot_id - root] = pivot_sum;
+            } else if let Some(leaf_idx) = vertex.pivot_leaf {
+                set_product(leaf_idx, pivot_sum);
+            }
+        }
+    }
+
+    /// Construct the inverse as dense matrix.
+    ///
+    /// The columns of the inverse are calculated by multiplying the inverse with the unit vectors, which results in a quadratic runtime.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let inverse = tree.inverse().unwrap().to_matrix();
+    ///
+    /// assert!((&matrix * inverse - ultrametric_matrix_tools::na::DMatrix::identity(4, 4)).norm() < 1e-12);
+    /// ```
+    pub fn to_matrix(&self) -> DMatrix<f64> {
+        let mut matrix = DMatrix::<f64>::zeros(self.size, self.size);
+        let mut unit_vector = DVector::<f64>::zeros(self.size);
+        for j in 0..self.size {
+            unit_vector[j] = 1.;
+            matrix.set_column(j, &self.mult_unchecked(&unit_vector));
+            unit_vector[j] = 0.;
+        }
+        return matrix;
+    }
+
+    /// Construct the inverse in compressed sparse row (CSR) format.
+    ///
+    /// The result consists of the row offsets, the column indices and the values of the non-zero elements of the inverse. The elements of row `i` are stored at the positions `row_offsets[i]..row_offsets[i + 1]` with increasing column indices. Below vertices with level zero, the matrix and thus the inverse are block diagonal. The blocks are the maximal subtrees whose root has a non-zero level. The elements between different blocks are structurally zero and are not computed. The elements of a block of size `m` are computed by `m` multiplications restricted to the block, such that the runtime is linear in the number of stored elements.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![2.0, 0.0, 0.0, 0.0, 4.0, 2.0, 0.0, 2.0, 4.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let (row_offsets, col_indices, values) = tree.inverse().unwrap().to_csr();
+    ///
+    /// assert_eq!(row_offsets, vec![0, 1, 3, 5]);
+    /// assert_eq!(col_indices, vec![0, 1, 2, 1, 2]);
+    /// assert!((values[0] - 0.5).abs() < 1e-12 && (values[2] + 1.0 / 6.0).abs() < 1e-12);
+    /// ```
+    pub fn to_csr(&self) -> (Vec<usize>, Vec<usize>, Vec<f64>) {
+        let mut blocks: Vec<usize> = Vec::new();
+        let mut stack: Vec<usize> = Vec::new();
+        if !self.vertices.is_empty() {
+            stack.push(0);
+        }
+        while let Some(id) = stack.pop() {
+            let vertex = &self.vertices[id];
+            if vertex.weight != 0.0 || vertex.pivot_leaf.is_some() {
+                blocks.push(id);
+                continue;
+            }
+            let mut child_id = id + 1;
+            while child_id < id + vertex.size {
+                stack.push(child_id);
+                child_id += self.vertices[child_id].size;
+            }
+        }
+
+        let mut block_indices: Vec<Vec<usize>> = Vec::with_capacity(blocks.len());
+        let mut positions: Vec<usize> = vec![0; self.size];
+        let mut row_offsets: Vec<usize> = vec![0; self.size + 1];
+        for &root in blocks.iter() {
+            let mut indices: Vec<usize> = self.vertices[root..root + self.vertices[root].size]
+                .iter()
+                .filter_map(|vertex| vertex.pivot_leaf)
+                .collect();
+            indices.sort_unstable();
+            for (position, &idx) in indices.iter().enumerate() {
+                positions[idx] = position;
+                row_offsets[idx + 1] = indices.len();
+            }
+            block_indices.push(indices);
+        }
+        for i in 0..self.size {
+            row_offsets[i + 1] += row_offsets[i];
+        }
+
+        let num_elements = row_offsets[self.size];
+        let mut col_indices: Vec<usize> = vec![0; num_elements];
+        let mut values: Vec<f64> = vec![0.; num_elements];
+        let mut unit_vector = DVector::<f64>::zeros(self.size);
+        let (mut d, mut sums, mut prev_sums) = (Vec::new(), Vec::new(), Vec::new());
+        for (&root, indices) in blocks.iter().zip(block_indices.iter()) {
+            let block_size = self.vertices[root].size;
+            d.resize(block_size, 0.);
+            sums.resize(block_size, 0.);
+            prev_sums.resize(block_size, 0.);
+            for &j in indices.iter() {
+                let row_start = row_offsets[j];
+                col_indices[row_start..row_start + indices.len()].copy_from_slice(indices);
+                unit_vector[j] = 1.;
+                self.mult_subtree(
+                    root,
+                    &unit_vector,
+                    &mut d,
+                    &mut sums,
+                    &mut prev_sums,
+                    |i, value| {
+                        values[row_start + positions[i]] = value;
+                    },
+                );
+                unit_vector[j] = 0.;
+            }
+        }
+        return (row_offsets, col_indices, values);
+    }
+}
+
+#[pymethods]
+impl UltrametricInverse {
+    /// Python wrapper for [`mult`](UltrametricInverse::mult).
+    ///
+    /// A `ValueError` is raised if the vector is not a 1-dimensional array of the size of the matrix.
+    #[pyo3(name = "mult")]
+    pub fn mult_py<'py>(
+        &self,
+        py: Python<'py>,
+        py_vector: PyReadonlyArrayDyn<f64>,
+    ) -> PyResult<&'py PyArray1<f64>> {
+        let vector = vector_from_py(&py_vector)?;
+        let product = self.mult(&vector)?;
+        let py_product = PyArray1::from_vec(py, product.data.as_vec().clone());
+        return Ok(py_product);
+    }
+
+    /// Python wrapper for [`to_matrix`](UltrametricInverse::to_matrix).
+    #[pyo3(name = "to_matrix")]
+    pub fn to_matrix_py<'py>(&self, py: Python<'py>) -> &'py PyArray2<f64> {
+        let matrix = self.to_matrix();
+        let mut py_matrix = Array2::zeros((self.size, self.size));
+        for i in 0..self.size {
+            for j in 0..self.size {
+                py_matrix[[i, j]] = matrix[(i, j)];
+            }
+        }
+        return py_matrix.into_pyarray(py);
+    }
+
+    /// Python wrapper for [`to_csr`](UltrametricInverse::to_csr).
+    #[pyo3(name = "to_csr")]
+    pub fn to_csr_py<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> (
+        &'py PyArray1<usize>,
+        &'py PyArray1<usize>,
+        &'py PyArray1<f64>,
+    ) {
+        let (row_offsets, col_indices, values) = self.to_csr();
+        return (
+            PyArray1::from_vec(py, row_offsets),
+            PyArray1::from_vec(py, col_indices),
+            PyArray1::from_vec(py, values),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UltrametricTree;
+
+    #[test]
+    fn mult_dimension_mismatch() {
+        let matrix = DMatrix::from_vec(3, 3, vec![4.0, 1.0, 3.0, 1.0, 4.0, 1.0, 3.0, 1.0, 4.0]);
+        let inverse = UltrametricTree::from_matrix(&matrix).inverse().unwrap();
+        assert!(inverse.mult(&DVector::zeros(3)).is_ok());
+        assert_eq!(
+            inverse.mult(&DVector::zeros(4)).err(),
+            Some(UltrametricError::DimensionMismatch {
+                expected: 3,
+                found: 4
+            })
+        );
+    }
+}
@@ -1,48 +1,184 @@
 //! `UltrametricTree` implementation.
 
-use nalgebra::{DMatrix, DVector};
+use crate::binary::{BINARY_FORMAT_VERSION, MAGIC};
+use crate::eigen::Spectrum;
+use crate::error::UltrametricError;
+use crate::lca::LcaIndex;
+use crate::newick::{parse_newick, quote_label};
+use crate::ultrametric_inverse::{InverseVertex, UltrametricInverse, SINGULAR_TOLERANCE};
+use crate::utils::{split_partition, split_partition_approx};
+use nalgebra::{DMatrix, DVector, Dynamic, SymmetricEigen};
 use ndarray::prelude::*;
 use numpy::{IntoPyArray, PyArray1, PyArray2, PyReadonlyArrayDyn};
 use ptree::builder::TreeBuilder;
-use ptree::output::print_tree;
+use ptree::item::StringItem;
+use ptree::output::write_tree;
+use pyo3::exceptions::{PyIndexError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::PyObjectProtocol;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
 use std::ops;
 
 /// Tree that represents the structure of an ultrametric matrix.
 ///
-/// Actually, `UltrametricTree` only represents one vertex of the tree. However, a vertex and its `children` represent the structure of an ultrametric tree. To reconstruct the original ultrametric matrix, the root vertex of the tree has to be stored.
+/// The vertices of the tree are stored in pre-order in one contiguous vector, such that the subtree of a vertex is a contiguous range of vertices. The indices of the matrix are stored in one global permutation, such that the `partition` of each vertex is a contiguous slice of the permutation. The `partition` of a vertex consists of the partitions of its children followed by its `partition_leaves`, which are the indices where the vertex is a leaf.
 #[pyclass]
 #[derive(Default, Clone)]
 pub struct UltrametricTree {
-    /// Vector storing indices associated with the vertex
-    partition: Vec<usize>,
-    /// Vector storing indices, where the vertex is a leaf
-    partition_leaves: Vec<usize>,
+    /// Vector storing the vertices in pre-order
+    pub(crate) vertices: Vec<Vertex>,
+    /// Vector storing the permutation of the indices, such that the `partition` of each vertex is a slice
+    pub(crate) permutation: Vec<usize>,
+    /// Vector storing for each index the vertex, where the index is a leaf
+    pub(crate) leaf_vertices: Vec<usize>,
+    /// Index for the lowest common ancestor of two vertices
+    pub(crate) lca_index: LcaIndex,
+}
+
+/// Vertex of an [`UltrametricTree`].
+#[derive(Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Vertex {
+    /// Value of the vertex
+    pub(crate) level: f64,
+    /// End of the subtree of the vertex in the vertices of the tree
+    pub(crate) subtree_end: usize,
+    /// Start of the `partition` of the vertex in the permutation of the tree
+    pub(crate) partition_start: usize,
+    /// Start of the `partition_leaves` of the vertex in the permutation of the tree
+    pub(crate) leaf_start: usize,
+    /// End of the `partition` of the vertex in the permutation of the tree
+    pub(crate) partition_end: usize,
+}
+
+/// Vertex used to construct an [`UltrametricTree`] from its explicit structure.
+#[derive(Default)]
+struct VertexBuilder {
     /// Value of the vertex
     level: f64,
-    /// Value of partial product used for multiplication
-    sum: f64,
-    /// Children vertices of the vertex
-    children: Vec<Box<UltrametricTree>>,
+    /// Positions of the children in the vector of `VertexBuilder`s
+    children: Vec<usize>,
+    /// Vector storing indices, where the vertex is a leaf
+    leaves: Vec<usize>,
+}
+
+/// Minimal number of indices in the `partition` of a vertex, such that the vertex is processed before the parallel subtrees with the `rayon` feature.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_SIZE: usize = 1 << 12;
+
+/// Minimal number of indices in the `partition` of a subtree, such that the subtree is processed in its own parallel task with the `rayon` feature.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_TASK_SIZE: usize = 1 << 8;
+
+/// Reusable memory for the multiplication in [`mult_into`](UltrametricTree::mult_into) and [`gemv_into`](UltrametricTree::gemv_into).
+///
+/// The workspace is not bound to a tree, but its memory only grows to the number of vertices of the largest tree that it is used with.
+#[derive(Default, Clone, Debug)]
+pub struct MultWorkspace {
+    /// Partial products of the vertices
+    sums: Vec<f64>,
+}
+
+/// Options for the Graphviz export in [`to_dot`](UltrametricTree::to_dot).
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DotOptions {
+    /// Maximal size of a `partition`, whose `partition_leaves` are listed in the label of the vertex. The leaves of larger partitions are collapsed to their number. All leaves are listed if this is `None`.
+    pub max_partition_size: Option<usize>,
+    /// Whether the vertices are filled with a color from blue for the lowest to red for the highest level
+    pub color_by_level: bool,
+}
+
+/// Position of the root of the dendrogram drawn by [`to_svg`](UltrametricTree::to_svg).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DendrogramOrientation {
+    /// The root is at the top and the leaves are at the bottom.
+    Top,
+    /// The root is at the bottom and the leaves are at the top.
+    Bottom,
+    /// The root is at the left and the leaves are at the right.
+    Left,
+    /// The root is at the right and the leaves are at the left.
+    Right,
 }
 
+/// Options for the dendrogram drawn by [`to_svg`](UltrametricTree::to_svg).
+#[derive(Clone, Debug)]
+pub struct SvgOptions {
+    /// Width of the image in pixels
+    pub width: f64,
+    /// Height of the image in pixels
+    pub height: f64,
+    /// Position of the root
+    pub orientation: DendrogramOrientation,
+    /// Labels of the indices, where indices without a label are labeled by their number. All indices are labeled by their number if this is `None`.
+    pub labels: Option<Vec<String>>,
+    /// Whether the labels are drawn
+    pub show_labels: bool,
+    /// Font size of the labels in pixels
+    pub font_size: f64,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            width: 800.0,
+            height: 400.0,
+            orientation: DendrogramOrientation::Top,
+            labels: None,
+            show_labels: true,
+            font_size: 12.0,
+        }
+    }
+}
+
+/// Options for the text representation of the tree written by [`write_tree`](UltrametricTree::write_tree).
+///
+/// Each vertex is written on one line that contains its `partition`, its `partition_leaves` and its `level`, optionally followed by the number of indices in its subtree. The default options give the same output as [`print_tree`](UltrametricTree::print_tree).
+#[derive(Clone, Copy, Debug)]
+pub struct PrintOptions {
+    /// Whether the `partition` of each vertex is shown
+    pub show_partition: bool,
+    /// Maximal number of indices that are shown in a list, where the remaining indices are replaced by their number. All indices are shown if this is `None`.
+    pub max_indices: Option<usize>,
+    /// Number of digits after the decimal point of the levels. The shortest representation is used if this is `None`.
+    pub precision: Option<usize>,
+    /// Whether the number of indices in the subtree of each vertex is shown
+    pub show_subtree_size: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            show_partition: true,
+            max_indices: None,
+            precision: None,
+            show_subtree_size: false,
+        }
+    }
+}
+
+/// Margin around the dendrogram drawn by [`to_svg`](UltrametricTree::to_svg) in pixels.
+const SVG_MARGIN: f64 = 10.0;
+
 /// Implementation of multiplication operator for `tree * &vector`.
 impl<'b> ops::Mul<&'b DVector<f64>> for UltrametricTree {
     type Output = DVector<f64>;
 
     fn mul(self, vector: &'b DVector<f64>) -> DVector<f64> {
-        let mut tree = self.clone();
-        tree.mult(vector)
+        self.mult(vector)
     }
 }
 
 /// Implementation of multiplication operator for `&tree * &vector`.
-impl<'a, 'b> ops::Mul<&'b DVector<f64>> for &'a UltrametricTree {
+impl<'b> ops::Mul<&'b DVector<f64>> for &UltrametricTree {
     type Output = DVector<f64>;
 
     fn mul(self, vector: &'b DVector<f64>) -> DVector<f64> {
-        let mut tree = self.clone();
-        tree.mult(vector)
+        self.mult(vector)
     }
 }
 
@@ -51,141 +187,2014 @@ impl ops::Mul<DVector<f64>> for UltrametricTree {
     type Output = DVector<f64>;
 
     fn mul(self, vector: DVector<f64>) -> DVector<f64> {
-        let mut tree = self.clone();
-        tree.mult(&vector)
+        self.mult(&vector)
     }
 }
 
 /// Implementation of multiplication operator for `&tree * vector`.
-impl<'a> ops::Mul<DVector<f64>> for &'a UltrametricTree {
+impl ops::Mul<DVector<f64>> for &UltrametricTree {
     type Output = DVector<f64>;
 
     fn mul(self, vector: DVector<f64>) -> DVector<f64> {
-        let mut tree = self.clone();
-        tree.mult(&vector)
+        self.mult(&vector)
+    }
+}
+
+/// Implementation of multiplication operator for `tree * &matrix`.
+impl<'b> ops::Mul<&'b DMatrix<f64>> for UltrametricTree {
+    type Output = DMatrix<f64>;
+
+    fn mul(self, matrix: &'b DMatrix<f64>) -> DMatrix<f64> {
+        self.mult_matrix(matrix)
+    }
+}
+
+/// Implementation of multiplication operator for `&tree * &matrix`.
+impl<'b> ops::Mul<&'b DMatrix<f64>> for &UltrametricTree {
+    type Output = DMatrix<f64>;
+
+    fn mul(self, matrix: &'b DMatrix<f64>) -> DMatrix<f64> {
+        self.mult_matrix(matrix)
+    }
+}
+
+/// Implementation of multiplication operator for `tree * matrix`.
+impl ops::Mul<DMatrix<f64>> for UltrametricTree {
+    type Output = DMatrix<f64>;
+
+    fn mul(self, matrix: DMatrix<f64>) -> DMatrix<f64> {
+        self.mult_matrix(&matrix)
+    }
+}
+
+/// Implementation of multiplication operator for `&tree * matrix`.
+impl ops::Mul<DMatrix<f64>> for &UltrametricTree {
+    type Output = DMatrix<f64>;
+
+    fn mul(self, matrix: DMatrix<f64>) -> DMatrix<f64> {
+        self.mult_matrix(&matrix)
+    }
+}
+
+/// Text representation of the tree with the default [`PrintOptions`], where the precision of the formatter is used for the levels.
+///
+/// # Example:
+///
+/// ```
+/// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+///     vec![2.0, 1.0, 1.0, 1.0, 2.0, 2.0, 1.0, 2.0, 2.0]);
+/// let mut tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+/// tree.prune_tree();
+///
+/// assert_eq!(format!("{:.1}", tree),
+///     "[0, 1, 2], [], 1.0\n├─ [0], [0], 2.0\n└─ [1, 2], [1, 2], 2.0\n");
+/// ```
+impl fmt::Display for UltrametricTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = PrintOptions {
+            precision: f.precision(),
+            ..Default::default()
+        };
+        let mut text: Vec<u8> = Vec::new();
+        self.write_tree(&mut text, &options)
+            .map_err(|_| fmt::Error)?;
+        return f.write_str(&String::from_utf8_lossy(&text));
+    }
+}
+
+/// Text representation of the tree that shows all information, i.e. the full partitions and the sizes of the subtrees.
+impl fmt::Debug for UltrametricTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = PrintOptions {
+            show_subtree_size: true,
+            ..Default::default()
+        };
+        writeln!(f, "UltrametricTree of size {}", self.size())?;
+        let mut text: Vec<u8> = Vec::new();
+        self.write_tree(&mut text, &options)
+            .map_err(|_| fmt::Error)?;
+        return f.write_str(&String::from_utf8_lossy(&text));
+    }
+}
+
+impl UltrametricTree {
+    /// Create a new tree for a matrix of size `size` without any vertices.
+    fn new(size: usize) -> Self {
+        UltrametricTree {
+            vertices: Vec::new(),
+            permutation: (0..size).collect(),
+            leaf_vertices: vec![0; size],
+            lca_index: LcaIndex::default(),
+        }
+    }
+
+    /// Number of rows and columns of the matrix represented by the `UltrametricTree`.
+    fn size(&self) -> usize {
+        return self.permutation.len();
+    }
+
+    /// Indices associated with the vertex `id`.
+    fn partition(&self, id: usize) -> &[usize] {
+        let vertex = &self.vertices[id];
+        return &self.permutation[vertex.partition_start..vertex.partition_end];
+    }
+
+    /// Indices, where the vertex `id` is a leaf.
+    fn partition_leaves(&self, id: usize) -> &[usize] {
+        let vertex = &self.vertices[id];
+        return &self.permutation[vertex.leaf_start..vertex.partition_end];
+    }
+
+    /// Iterator over the children of the vertex `id`.
+    fn children(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        let subtree_end = self.vertices[id].subtree_end;
+        let first_child = if id + 1 < subtree_end {
+            Some(id + 1)
+        } else {
+            None
+        };
+        return std::iter::successors(first_child, move |&child| {
+            let next_child = self.vertices[child].subtree_end;
+            if next_child < subtree_end {
+                Some(next_child)
+            } else {
+                None
+            }
+        });
+    }
+
+    /// Differences of the levels of the vertices and the levels of their parents, where the level of the parent of the root is `0`.
+    fn weights(&self) -> Vec<f64> {
+        let mut weights: Vec<f64> = self.vertices.iter().map(|vertex| vertex.level).collect();
+        for id in 0..self.vertices.len() {
+            for child in self.children(id) {
+                weights[child] -= self.vertices[id].level;
+            }
+        }
+        return weights;
+    }
+
+    /// Construct an `UltrametricTree` for a matrix of size `size` from its explicit structure, where `root` is the position of the root in `builders`.
+    ///
+    /// The builders are traversed in pre-order using an explicit stack. Each builder is visited twice: once to create the vertex and once after its subtree is complete to append its leaves to the permutation.
+    fn from_builders(builders: &[VertexBuilder], root: usize, size: usize) -> Self {
+        let mut tree = UltrametricTree::new(size);
+        tree.permutation.clear();
+        let mut stack: Vec<(usize, Option<usize>)> = vec![(root, None)];
+        while let Some((builder_id, vertex_id)) = stack.pop() {
+            let builder = &builders[builder_id];
+            match vertex_id {
+                None => {
+                    let id = tree.vertices.len();
+                    tree.vertices.push(Vertex {
+                        level: builder.level,
+                        partition_start: tree.permutation.len(),
+                        ..Default::default()
+                    });
+                    stack.push((builder_id, Some(id)));
+                    for &child in builder.children.iter().rev() {
+                        stack.push((child, None));
+                    }
+                }
+                Some(id) => {
+                    tree.vertices[id].leaf_start = tree.permutation.len();
+                    for &leaf_idx in builder.leaves.iter() {
+                        tree.permutation.push(leaf_idx);
+                        tree.leaf_vertices[leaf_idx] = id;
+                    }
+                    tree.vertices[id].partition_end = tree.permutation.len();
+                    tree.vertices[id].subtree_end = tree.vertices.len();
+                }
+            }
+        }
+        tree.lca_index = LcaIndex::new(&tree.vertices);
+        return tree;
+    }
+
+    /// Construct an `UltrametricTree` from its vertices in pre-order and its permutation and check the invariants of the representation.
+    ///
+    /// The permutation has to contain each index exactly once and the levels must not be NaN. The subtree of each vertex has to be a non-empty range of vertices that is contained in the subtree of its parent, where the subtree of the root contains all vertices. The `partition` of each vertex has to consist of the partitions of its children followed by its `partition_leaves`, where the `partition` of the root contains all indices. Otherwise, [`UltrametricError::InvalidTree`] is returned. This is used to load trees that were stored by the user.
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_parts(
+        vertices: Vec<Vertex>,
+        permutation: Vec<usize>,
+    ) -> Result<Self, UltrametricError> {
+        let size = permutation.len();
+        let mut tree = UltrametricTree::new(size);
+        let mut is_seen = vec![false; size];
+        for &idx in permutation.iter() {
+            if idx >= size || is_seen[idx] {
+                return Err(UltrametricError::InvalidTree {
+                    reason: "the permutation is not a permutation of the indices",
+                });
+            }
+            is_seen[idx] = true;
+        }
+        if let Some(root) = vertices.first() {
+            if root.subtree_end != vertices.len()
+                || root.partition_start != 0
+                || root.partition_end != size
+            {
+                return Err(UltrametricError::InvalidTree {
+                    reason: "the root does not contain all vertices and indices",
+                });
+            }
+        } else if size > 0 {
+            return Err(UltrametricError::InvalidTree {
+                reason: "the tree has indices but no vertices",
+            });
+        }
+        for (id, vertex) in vertices.iter().enumerate() {
+            if vertex.level.is_nan() {
+                return Err(UltrametricError::InvalidTree {
+                    reason: "the level of a vertex is NaN",
+                });
+            }
+            if vertex.leaf_start < vertex.partition_start
+                || vertex.partition_end < vertex.leaf_start
+            {
+                return Err(UltrametricError::InvalidTree {
+                    reason: "the partition of a vertex is not a valid range",
+                });
+            }
+            let mut child = id + 1;
+            let mut partition_end = vertex.partition_start;
+            while child < vertex.subtree_end {
+                if vertices[child].subtree_end <= child
+                    || vertices[child].subtree_end > vertex.subtree_end
+                {
+                    return Err(UltrametricError::InvalidTree {
+                        reason: "the subtree of a vertex is not contained in its parent",
+                    });
+                }
+                if vertices[child].partition_start != partition_end {
+                    return Err(UltrametricError::InvalidTree {
+                        reason: "the partitions of the children of a vertex are not contiguous",
+                    });
+                }
+                partition_end = vertices[child].partition_end;
+                child = vertices[child].subtree_end;
+            }
+            if partition_end != vertex.leaf_start {
+                return Err(UltrametricError::InvalidTree {
+                    reason: "the partitions of the children of a vertex are not contiguous",
+                });
+            }
+        }
+        for (id, vertex) in vertices.iter().enumerate() {
+            for &leaf_idx in permutation[vertex.leaf_start..vertex.partition_end].iter() {
+                tree.leaf_vertices[leaf_idx] = id;
+            }
+        }
+        tree.lca_index = LcaIndex::new(&vertices);
+        tree.vertices = vertices;
+        tree.permutation = permutation;
+        return Ok(tree);
+    }
+
+    /// Construct a `UltrametricTree` from an ultrametric matrix that represents the structure of the matrix.
+    ///
+    /// This function does not check if the matrix is ultrametric, see [`try_from_matrix`](UltrametricTree::try_from_matrix) for a checked version. The value retured by this function is the root of the tree that represents the ultrametric matrix `matrix`. Thus, the function returns the tree that represents `matrix`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// ```
+    pub fn from_matrix(matrix: &DMatrix<f64>) -> Self {
+        return UltrametricTree::from_matrix_split(matrix, |partition| {
+            return split_partition(matrix, partition);
+        });
+    }
+
+    /// Construct a `UltrametricTree` from a matrix and check that the matrix is ultrametric.
+    ///
+    /// The matrix has to be non-empty, square, symmetric, free of NaN and its off-diagonal elements have to be non-negative. Additionally, all blocks of the partitions computed by [`from_matrix`](UltrametricTree::from_matrix) have to be constant and the levels have to increase from the root to the leaves. Otherwise, the corresponding [`UltrametricError`] is returned, which contains the indices of the offending elements. For a violation of the ultrametric inequality, these are the indices `(i, j, k)` with `a[(i, j)] < min(a[(i, k)], a[(k, j)])`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let not_ultrametric = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 2.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 2.0, 1.0, 1.0, 1.0]);
+    ///
+    /// assert!(ultrametric_matrix_tools::UltrametricTree::try_from_matrix(&matrix).is_ok());
+    /// assert_eq!(ultrametric_matrix_tools::UltrametricTree::try_from_matrix(&not_ultrametric).err(),
+    ///     Some(ultrametric_matrix_tools::UltrametricError::NonUltrametric { i: 2, j: 3, k: 0 }));
+    /// ```
+    pub fn try_from_matrix(matrix: &DMatrix<f64>) -> Result<Self, UltrametricError> {
+        UltrametricTree::check_elements(matrix)?;
+        let tree = UltrametricTree::from_matrix(matrix);
+        tree.check_blocks(matrix)?;
+        return Ok(tree);
+    }
+
+    /// Check the shape and the elements of a matrix, which is used in [`try_from_matrix`](UltrametricTree::try_from_matrix).
+    fn check_elements(matrix: &DMatrix<f64>) -> Result<(), UltrametricError> {
+        let (rows, columns) = matrix.shape();
+        if rows == 0 || columns == 0 {
+            return Err(UltrametricError::EmptyMatrix);
+        }
+        if rows != columns {
+            return Err(UltrametricError::NonSquareMatrix { rows, columns });
+        }
+        for row in 0..rows {
+            for column in 0..columns {
+                if matrix[(row, column)].is_nan() {
+                    return Err(UltrametricError::NotANumber { row, column });
+                }
+            }
+        }
+        for row in 0..rows {
+            for column in (row + 1)..columns {
+                if matrix[(row, column)] != matrix[(column, row)] {
+                    return Err(UltrametricError::NonSymmetric { row, column });
+                }
+                if matrix[(row, column)] < 0.0 {
+                    return Err(UltrametricError::NegativeElement { row, column });
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Check that the blocks of the partitions of the tree are constant and that the levels increase, which is used in [`try_from_matrix`](UltrametricTree::try_from_matrix).
+    ///
+    /// The tree has to be constructed by [`from_matrix`](UltrametricTree::from_matrix), such that the first index of the `partition` of a vertex is the index that was used to split the `partition`.
+    fn check_blocks(&self, matrix: &DMatrix<f64>) -> Result<(), UltrametricError> {
+        for (id, vertex) in self.vertices.iter().enumerate() {
+            let first_i = self.permutation[vertex.partition_start];
+            for child in self.children(id) {
+                let child_vertex = &self.vertices[child];
+                let rest = &self.permutation[child_vertex.partition_end..vertex.partition_end];
+                for &i in self.partition(child).iter() {
+                    for &j in rest.iter() {
+                        if matrix[(i, j)] < vertex.level {
+                            return Err(UltrametricError::NonUltrametric { i, j, k: first_i });
+                        } else if matrix[(i, j)] > vertex.level {
+                            return Err(UltrametricError::NonUltrametric {
+                                i: first_i,
+                                j,
+                                k: i,
+                            });
+                        }
+                    }
+                }
+                if child + 1 < child_vertex.subtree_end && child_vertex.level < vertex.level {
+                    let right_child = self.vertices[child + 1].subtree_end;
+                    let k = if child_vertex.partition_start == vertex.partition_start {
+                        rest[0]
+                    } else {
+                        first_i
+                    };
+                    return Err(UltrametricError::NonUltrametric {
+                        i: self.permutation[child_vertex.partition_start],
+                        j: self.permutation[self.vertices[right_child].partition_start],
+                        k,
+                    });
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Construct a `UltrametricTree` from an ultrametric matrix that approximately represents the structure of the matrix.
+    ///
+    /// This function is similar to [`from_matrix`](UltrametricTree::from_matrix). The difference is that the elements associated with the indices in the right_partition are at most `min + eps` instead of equal to `min`. Only the minimal value is stored in the vertex. If [`is_ultrametric_with_tolerance`](crate::utils::is_ultrametric_with_tolerance) accepts the matrix with `abs_tol = eps`, then the tree reproduces each element of the matrix within the tolerance of the check.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.1, 3.0, 1.0, 1.1, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix_approx(&matrix, 0.1);
+    /// ```
+    pub fn from_matrix_approx(matrix: &DMatrix<f64>, eps: f64) -> Self {
+        return UltrametricTree::from_matrix_split(matrix, |partition| {
+            return split_partition_approx(matrix, partition, eps);
+        });
+    }
+
+    /// Construct the `UltrametricTree` of the subdominant ultrametric approximation of a symmetric matrix.
+    ///
+    /// The off-diagonal elements of the matrix are interpreted as similarities, such that the approximation is the smallest ultrametric matrix whose off-diagonal elements are at least the elements of `matrix`, while the diagonal is kept. For the distances `c - a`, this is the subdominant ultrametric, i.e. the largest ultrametric bounded entrywise by the distances. Each off-diagonal element of the approximation is the maximum over all paths between the indices of the minimal element on the path, which is computed by single linkage on a maximum spanning tree. The maximum spanning tree is computed by Prim's algorithm in quadratic time. The function returns the tree together with the approximation error, which is the maximal absolute difference of the elements of `matrix` and the approximation. If `matrix` is not square, then [`UltrametricError::NonSquareMatrix`] is returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![5.0, 2.0, 1.0, 2.0, 5.0, 3.0, 1.0, 3.0, 5.0]);
+    /// let (tree, error) = ultrametric_matrix_tools::UltrametricTree::subdominant(&matrix).unwrap();
+    ///
+    /// assert_eq!(tree.to_matrix(), ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![5.0, 2.0, 2.0, 2.0, 5.0, 3.0, 2.0, 3.0, 5.0]));
+    /// assert_eq!(error, 1.0);
+    /// ```
+    pub fn subdominant(matrix: &DMatrix<f64>) -> Result<(Self, f64), UltrametricError> {
+        if !matrix.is_square() {
+            return Err(UltrametricError::NonSquareMatrix {
+                rows: matrix.nrows(),
+                columns: matrix.ncols(),
+            });
+        }
+        let size = matrix.nrows();
+        if size == 0 {
+            return Ok((UltrametricTree::new(0), 0.0));
+        }
+        let mut in_tree = vec![false; size];
+        let mut best_weight = vec![f64::NEG_INFINITY; size];
+        let mut best_neighbor = vec![0; size];
+        let mut edges: Vec<(f64, usize, usize)> = Vec::with_capacity(size - 1);
+        let mut next = 0;
+        for _ in 1..size {
+            in_tree[next] = true;
+            let current = next;
+            let mut max_weight = f64::NEG_INFINITY;
+            for j in 0..size {
+                if in_tree[j] {
+                    continue;
+                }
+                if matrix[(current, j)] > best_weight[j] {
+                    best_weight[j] = matrix[(current, j)];
+                    best_neighbor[j] = current;
+                }
+                if best_weight[j] >= max_weight {
+                    max_weight = best_weight[j];
+                    next = j;
+                }
+            }
+            edges.push((best_weight[next], best_neighbor[next], next));
+        }
+        edges.sort_by(|edge1, edge2| edge2.0.total_cmp(&edge1.0));
+
+        let mut builders: Vec<VertexBuilder> = (0..size)
+            .map(|i| VertexBuilder {
+                level: matrix[(i, i)],
+                children: Vec::new(),
+                leaves: vec![i],
+            })
+            .collect();
+        let mut cluster_builders: Vec<usize> = (0..size).collect();
+        let mut cluster_members: Vec<Vec<usize>> = (0..size).map(|i| vec![i]).collect();
+        let mut cluster_of: Vec<usize> = (0..size).collect();
+        let mut error: f64 = 0.;
+        for &(weight, i, j) in edges.iter() {
+            let (mut cluster1, mut cluster2) = (cluster_of[i], cluster_of[j]);
+            if cluster_members[cluster1].len() < cluster_members[cluster2].len() {
+                std::mem::swap(&mut cluster1, &mut cluster2);
+            }
+            let members2 = std::mem::take(&mut cluster_members[cluster2]);
+            for &i1 in cluster_members[cluster1].iter() {
+                for &i2 in members2.iter() {
+                    error = f64::max(error, (matrix[(i1, i2)] - weight).abs());
+                    error = f64::max(error, (matrix[(i2, i1)] - weight).abs());
+                }
+            }
+            for &i2 in members2.iter() {
+                cluster_of[i2] = cluster1;
+            }
+            cluster_members[cluster1].extend(members2);
+            builders.push(VertexBuilder {
+                level: weight,
+                children: vec![cluster_builders[cluster1], cluster_builders[cluster2]],
+                leaves: Vec::new(),
+            });
+            cluster_builders[cluster1] = builders.len() - 1;
+        }
+        let root = builders.len() - 1;
+        return Ok((UltrametricTree::from_builders(&builders, root, size), error));
+    }
+
+    /// Construct the `UltrametricTree` of the ultrametric matrix closest to a symmetric matrix in the maximum norm.
+    ///
+    /// An ultrametric matrix within distance `delta` of `matrix` exists if and only if the smallest ultrametric matrix above `matrix - delta` is at most `matrix + delta`. Since the smallest ultrametric matrix above a matrix is the [`subdominant`](UltrametricTree::subdominant) approximation and shifting commutes with it, the optimal distance is half of the error of the subdominant approximation, which is the unconstrained case of the algorithm of Farach, Kannan and Warnow[^Farach et al., 1995]. Since the off-diagonal elements of an ultrametric matrix are non-negative, the distance is at least the negated minimal off-diagonal element and the levels are clamped at zero. The diagonal is kept, such that the function returns the tree together with the achieved error, which is the maximal absolute difference of the off-diagonal elements. The runtime is quadratic. If `matrix` is not square, then [`UltrametricError::NonSquareMatrix`] is returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![5.0, 2.0, 1.0, 2.0, 5.0, 3.0, 1.0, 3.0, 5.0]);
+    /// let (tree, error) = ultrametric_matrix_tools::UltrametricTree::linf_fit(&matrix).unwrap();
+    ///
+    /// assert_eq!(tree.to_matrix(), ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![5.0, 1.5, 1.5, 1.5, 5.0, 2.5, 1.5, 2.5, 5.0]));
+    /// assert_eq!(error, 0.5);
+    /// ```
+    ///
+    /// [^Farach et al., 1995]: [Farach, M., Kannan, S. and Warnow, T., 1995. A robust model for finding optimal evolutionary trees. Algorithmica, 13(1), pp.155-179.](https://doi.org/10.1007/BF01188585)
+    pub fn linf_fit(matrix: &DMatrix<f64>) -> Result<(Self, f64), UltrametricError> {
+        let (mut tree, subdominant_error) = UltrametricTree::subdominant(matrix)?;
+        let size = matrix.nrows();
+        let mut error = 0.5 * subdominant_error;
+        for i in 0..size {
+            for j in 0..size {
+                if i != j {
+                    error = f64::max(error, -matrix[(i, j)]);
+                }
+            }
+        }
+        for id in 0..tree.vertices.len() {
+            if id + 1 < tree.vertices[id].subtree_end {
+                tree.vertices[id].level = f64::max(tree.vertices[id].level - error, 0.0);
+            }
+        }
+        return Ok((tree, error));
+    }
+
+    /// Construct the `UltrametricTree` of an ultrametric matrix that approximates a matrix in the weighted least-squares sense.
+    ///
+    /// The topology of the tree is computed by average linkage (UPGMA), where the two clusters with the largest weighted average of the elements between them are merged. The nearest-neighbor chain algorithm is used, such that the topology is computed in quadratic time. For the fixed topology, the weighted least-squares fit of the level of each vertex is the weighted average of the elements between its children, which is refined to be non-negative. Since average linkage has no inversions, the levels increase from the root to the leaves. Vertices, whose elements all have zero weight, get the level of their parent. The diagonal is kept. If `weights` is `None`, then all elements have weight one. The function returns the tree together with the weighted Frobenius norm of the residual `sqrt(sum(w_ij * (a_ij - u_ij)^2))`. If `matrix` is not square, then [`UltrametricError::NonSquareMatrix`] is returned. If the number of rows or columns of `weights` does not match the size of `matrix`, then [`UltrametricError::DimensionMismatch`] is returned with the mismatching dimension.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![5.0, 2.0, 1.0, 2.0, 5.0, 3.0, 1.0, 3.0, 5.0]);
+    /// let (tree, residual) = ultrametric_matrix_tools::UltrametricTree::least_squares_fit(&matrix, None).unwrap();
+    ///
+    /// assert_eq!(tree.to_matrix(), ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![5.0, 1.5, 1.5, 1.5, 5.0, 3.0, 1.5, 3.0, 5.0]));
+    /// assert_eq!(residual, 1.0);
+    /// ```
+    pub fn least_squares_fit(
+        matrix: &DMatrix<f64>,
+        weights: Option<&DMatrix<f64>>,
+    ) -> Result<(Self, f64), UltrametricError> {
+        if !matrix.is_square() {
+            return Err(UltrametricError::NonSquareMatrix {
+                rows: matrix.nrows(),
+                columns: matrix.ncols(),
+            });
+        }
+        let size = matrix.nrows();
+        if let Some(weights) = weights {
+            for found in [weights.nrows(), weights.ncols()] {
+                if found != size {
+                    return Err(UltrametricError::DimensionMismatch {
+                        expected: size,
+                        found,
+                    });
+                }
+            }
+        }
+        if size == 0 {
+            return Ok((UltrametricTree::new(0), 0.0));
+        }
+        let weight = |i: usize, j: usize| weights.map_or(1.0, |weights| weights[(i, j)]);
+        let mut block_sums = DMatrix::<f64>::zeros(size, size);
+        let mut block_weights = DMatrix::<f64>::zeros(size, size);
+        for i in 0..size {
+            for j in 0..size {
+                if i != j {
+                    block_sums[(i, j)] =
+                        weight(i, j) * matrix[(i, j)] + weight(j, i) * matrix[(j, i)];
+                    block_weights[(i, j)] = weight(i, j) + weight(j, i);
+                }
+            }
+        }
+        let average = |sums: &DMatrix<f64>, weights: &DMatrix<f64>, i: usize, j: usize| {
+            if weights[(i, j)] > 0.0 {
+                sums[(i, j)] / weights[(i, j)]
+            } else {
+                f64::NEG_INFINITY
+            }
+        };
+
+        let mut builders: Vec<VertexBuilder> = (0..size)
+            .map(|i| VertexBuilder {
+                level: matrix[(i, i)],
+                children: Vec::new(),
+                leaves: vec![i],
+            })
+            .collect();
+        let mut cluster_builders: Vec<usize> = (0..size).collect();
+        let mut cluster_members: Vec<Vec<usize>> = (0..size).map(|i| vec![i]).collect();
+        let mut active: Vec<bool> = vec![true; size];
+        let mut has_level: Vec<bool> = vec![true; size];
+        let mut parents: Vec<usize> = vec![0; size];
+        let mut residual = 0.;
+        let mut chain: Vec<usize> = Vec::new();
+        for _ in 1..size {
+            loop {
+                if chain.is_empty() {
+                    chain.push(active.iter().position(|&is_active| is_active).unwrap());
+                }
+                let cluster1 = chain[chain.len() - 1];
+                let previous = if chain.len() > 1 {
+                    Some(chain[chain.len() - 2])
+                } else {
+                    None
+                };
+                let mut nearest = previous;
+                let mut max_average = previous.map_or(f64::NEG_INFINITY, |cluster2| {
+                    average(&block_sums, &block_weights, cluster1, cluster2)
+                });
+                for (cluster2, &is_active) in active.iter().enumerate() {
+                    if !is_active || cluster2 == cluster1 {
+                        continue;
+                    }
+                    let value = average(&block_sums, &block_weights, cluster1, cluster2);
+                    if nearest.is_none() || value > max_average {
+                        nearest = Some(cluster2);
+                        max_average = value;
+                    }
+                }
+                let cluster2 = nearest.unwrap();
+                if Some(cluster2) != previous {
+                    chain.push(cluster2);
+                    continue;
+                }
+                chain.truncate(chain.len() - 2);
+
+                let level = f64::max(max_average, 0.0);
+                let members2 = std::mem::take(&mut cluster_members[cluster2]);
+                if block_weights[(cluster1, cluster2)] > 0.0 {
+                    for &i1 in cluster_members[cluster1].iter() {
+                        for &i2 in members2.iter() {
+                            residual += weight(i1, i2) * (matrix[(i1, i2)] - level).powi(2);
+                            residual += weight(i2, i1) * (matrix[(i2, i1)] - level).powi(2);
+                        }
+                    }
+                }
+                cluster_members[cluster1].extend(members2);
+                for cluster3 in 0..size {
+                    if active[cluster3] && cluster3 != cluster1 && cluster3 != cluster2 {
+                        let sum =
+                            block_sums[(cluster1, cluster3)] + block_sums[(cluster2, cluster3)];
+                        let block_weight = block_weights[(cluster1, cluster3)]
+                            + block_weights[(cluster2, cluster3)];
+                        block_sums[(cluster1, cluster3)] = sum;
+                        block_sums[(cluster3, cluster1)] = sum;
+                        block_weights[(cluster1, cluster3)] = block_weight;
+                        block_weights[(cluster3, cluster1)] = block_weight;
+                    }
+                }
+                active[cluster2] = false;
+                let builder_id = builders.len();
+                for child in [cluster_builders[cluster1], cluster_builders[cluster2]] {
+                    parents[child] = builder_id;
+                }
+                builders.push(VertexBuilder {
+                    level,
+                    children: vec![cluster_builders[cluster1], cluster_builders[cluster2]],
+                    leaves: Vec::new(),
+                });
+                has_level.push(block_weights[(cluster1, cluster2)] > 0.0);
+                parents.push(builder_id);
+                cluster_builders[cluster1] = builder_id;
+                break;
+            }
+        }
+        let root = builders.len() - 1;
+        for builder_id in (size..builders.len()).rev() {
+            if !has_level[builder_id] {
+                builders[builder_id].level = if builder_id == root {
+                    0.0
+                } else {
+                    builders[parents[builder_id]].level
+                };
+            }
+        }
+        return Ok((
+            UltrametricTree::from_builders(&builders, root, size),
+            residual.sqrt(),
+        ));
+    }
+
+    /// Construct a `UltrametricTree` from a linkage matrix in the format of `scipy.cluster.hierarchy`.
+    ///
+    /// The linkage matrix of `n` indices has `n - 1` rows and `4` columns. Row `k` merges the clusters in the first two columns at the distance in the third column into the cluster `n + k`, where the clusters `0` to `n - 1` are the single indices. The fourth column contains the number of indices in the merged cluster. The distances are converted to levels by the similarity convention `level = c - distance`, such that the diagonal elements are `c`. Thus, no matrix has to be constructed. If the linkage matrix does not have `4` columns, then [`UltrametricError::DimensionMismatch`] is returned. If a row references an unknown or already merged cluster, has a wrong number of indices or decreases the distance, then [`UltrametricError::InvalidLinkage`] is returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let linkage = ultrametric_matrix_tools::na::DMatrix::from_row_slice(2, 4,
+    ///     &[0.0, 2.0, 1.0, 2.0, 1.0, 3.0, 3.0, 3.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_linkage(&linkage, 4.0).unwrap();
+    ///
+    /// assert_eq!(tree.to_matrix(), ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![4.0, 1.0, 3.0, 1.0, 4.0, 1.0, 3.0, 1.0, 4.0]));
+    /// ```
+    pub fn from_linkage(linkage: &DMatrix<f64>, c: f64) -> Result<Self, UltrametricError> {
+        if linkage.ncols() != 4 {
+            return Err(UltrametricError::DimensionMismatch {
+                expected: 4,
+                found: linkage.ncols(),
+            });
+        }
+        let size = linkage.nrows() + 1;
+        let mut builders: Vec<VertexBuilder> = (0..size)
+            .map(|i| VertexBuilder {
+                level: c,
+                children: Vec::new(),
+                leaves: vec![i],
+            })
+            .collect();
+        let mut cluster_sizes: Vec<usize> = vec![1; size];
+        let mut is_merged: Vec<bool> = vec![false; size];
+        for row in 0..linkage.nrows() {
+            let level = c - linkage[(row, 2)];
+            let mut children: Vec<usize> = Vec::new();
+            for column in 0..2 {
+                let cluster = linkage[(row, column)];
+                let is_valid_cluster = cluster >= 0.0
+                    && cluster.fract() == 0.0
+                    && (cluster as usize) < builders.len()
+                    && !is_merged[cluster as usize]
+                    && !children.contains(&(cluster as usize))
+                    && builders[cluster as usize].level >= level;
+                if !is_valid_cluster {
+                    return Err(UltrametricError::InvalidLinkage { row });
+                }
+                children.push(cluster as usize);
+            }
+            let cluster_size = cluster_sizes[children[0]] + cluster_sizes[children[1]];
+            if linkage[(row, 3)] != cluster_size as f64 || level.is_nan() {
+                return Err(UltrametricError::InvalidLinkage { row });
+            }
+            for &child in children.iter() {
+                is_merged[child] = true;
+            }
+            builders.push(VertexBuilder {
+                level,
+                children,
+                leaves: Vec::new(),
+            });
+            cluster_sizes.push(cluster_size);
+            is_merged.push(false);
+        }
+        let root = builders.len() - 1;
+        return Ok(UltrametricTree::from_builders(&builders, root, size));
+    }
+
+    /// Construct the tree of a matrix by repeatedly splitting the partitions with `split`.
+    ///
+    /// The function `split` returns the level of the vertex and the left and right partition of a partition with more than one index. Since each split creates two children, the subtree of a partition of size `k` consists of `2 * k - 1` vertices. Thus, the position of each vertex is known in advance and the partitions are split in any order. With the `rayon` feature, the partitions with at least [`PARALLEL_MIN_SIZE`] indices are split first and the remaining subtrees are constructed in parallel. Since the splits do not depend on their order, the tree does not depend on the number of threads.
+    fn from_matrix_split<F>(matrix: &DMatrix<f64>, split: F) -> Self
+    where
+        F: Fn(&[usize]) -> (f64, Vec<usize>, Vec<usize>) + Sync,
+    {
+        let size = matrix.nrows();
+        let mut tree = UltrametricTree::new(size);
+        tree.vertices = vec![Vertex::default(); (2 * size).saturating_sub(1)];
+        if size == 0 {
+            return tree;
+        }
+        #[cfg(not(feature = "rayon"))]
+        UltrametricTree::split_subtree(
+            matrix,
+            &split,
+            &mut tree.vertices,
+            &mut tree.permutation,
+            (0, 0, size),
+            0,
+        );
+        #[cfg(feature = "rayon")]
+        {
+            let subtrees = UltrametricTree::split_subtree(
+                matrix,
+                &split,
+                &mut tree.vertices,
+                &mut tree.permutation,
+                (0, 0, size),
+                PARALLEL_MIN_SIZE,
+            );
+            let mut tasks = Vec::with_capacity(subtrees.len());
+            let mut rest_vertices = &mut tree.vertices[..];
+            let mut rest_permutation = &mut tree.permutation[..];
+            let (mut vertex_offset, mut index_offset) = (0, 0);
+            for &(id, partition_start, partition_end) in subtrees.iter() {
+                let subtree_end = id + 2 * (partition_end - partition_start) - 1;
+                let (_, vertices) =
+                    std::mem::take(&mut rest_vertices).split_at_mut(id - vertex_offset);
+                let (vertices, next_vertices) = vertices.split_at_mut(subtree_end - id);
+                let (_, permutation) = std::mem::take(&mut rest_permutation)
+                    .split_at_mut(partition_start - index_offset);
+                let (permutation, next_permutation) =
+                    permutation.split_at_mut(partition_end - partition_start);
+                tasks.push(((id, partition_start, partition_end), vertices, permutation));
+                rest_vertices = next_vertices;
+                rest_permutation = next_permutation;
+                vertex_offset = subtree_end;
+                index_offset = partition_end;
+            }
+            tasks
+                .into_par_iter()
+                .for_each(|(subtree, vertices, permutation)| {
+                    UltrametricTree::split_subtree(
+                        matrix,
+                        &split,
+                        vertices,
+                        permutation,
+                        subtree,
+                        0,
+                    );
+                });
+        }
+
+        for (id, vertex) in tree.vertices.iter().enumerate() {
+            for &leaf_idx in tree.permutation[vertex.leaf_start..vertex.partition_end].iter() {
+                tree.leaf_vertices[leaf_idx] = id;
+            }
+        }
+        tree.lca_index = LcaIndex::new(&tree.vertices);
+        return tree;
+    }
+
+    /// Split the partitions of the subtree `(root, partition_start, partition_end)` using an explicit stack, where `vertices` starts at the vertex `root` and `permutation` starts at the index `partition_start`.
+    ///
+    /// Subtrees with less than `min_size` indices in their `partition` are not split, but returned in pre-order.
+    fn split_subtree<F>(
+        matrix: &DMatrix<f64>,
+        split: &F,
+        vertices: &mut [Vertex],
+        permutation: &mut [usize],
+        subtree: (usize, usize, usize),
+        min_size: usize,
+    ) -> Vec<(usize, usize, usize)>
+    where
+        F: Fn(&[usize]) -> (f64, Vec<usize>, Vec<usize>),
+    {
+        let (root, root_start, _) = subtree;
+        let mut subtrees: Vec<(usize, usize, usize)> = Vec::new();
+        let mut stack: Vec<(usize, usize, usize)> = vec![subtree];
+        while let Some((id, partition_start, partition_end)) = stack.pop() {
+            if partition_end - partition_start < min_size {
+                subtrees.push((id, partition_start, partition_end));
+                continue;
+            }
+            let vertex = &mut vertices[id - root];
+            vertex.partition_start = partition_start;
+            vertex.leaf_start = partition_end;
+            vertex.partition_end = partition_end;
+            vertex.subtree_end = id + 2 * (partition_end - partition_start) - 1;
+            let partition =
+                &mut permutation[(partition_start - root_start)..(partition_end - root_start)];
+            if partition.len() == 1 {
+                vertex.level = matrix[(partition[0], partition[0])];
+                vertex.leaf_start = partition_start;
+                continue;
+            }
+            let (level, left_partition, right_partition) = split(partition);
+            vertex.level = level;
+            let (left, right) = partition.split_at_mut(left_partition.len());
+            left.copy_from_slice(&left_partition);
+            right.copy_from_slice(&right_partition);
+
+            let partition_split = partition_start + left_partition.len();
+            let right_id = id + 2 * left_partition.len();
+            stack.push((right_id, partition_split, partition_end));
+            stack.push((id + 1, partition_start, partition_split));
+        }
+        return subtrees;
+    }
+
+    /// Construct the ultrametric matrix that is represented by the `UltrametricTree`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let reconstructed_matrix = tree.to_matrix();
+    ///
+    /// assert_eq!(matrix, reconstructed_matrix);
+    /// ```
+    pub fn to_matrix(&self) -> DMatrix<f64> {
+        let size = self.size();
+        let mut matrix = DMatrix::<f64>::zeros(size, size);
+        for (id, vertex) in self.vertices.iter().enumerate() {
+            for child in self.children(id) {
+                let child_end = self.vertices[child].partition_end;
+                for &i1 in self.partition(child).iter() {
+                    for &i2 in self.permutation[child_end..vertex.partition_end].iter() {
+                        matrix[(i1, i2)] = vertex.level;
+                        matrix[(i2, i1)] = vertex.level;
+                    }
+                }
+            }
+            for &leaf1 in self.partition_leaves(id).iter() {
+                for &leaf2 in self.partition_leaves(id).iter() {
+                    matrix[(leaf1, leaf2)] = vertex.level;
+                }
+            }
+        }
+        return matrix;
+    }
+
+    /// Construct a `UltrametricTree` from a tree in the Newick format.
+    ///
+    /// The level of each vertex is its depth, which is the sum of the branch lengths from the root to the vertex including the branch length of the root. Missing branch lengths are zero and negative branch lengths are allowed, such that the trees written by [`to_newick`](UltrametricTree::to_newick) are read back for levels that decrease from a vertex to its children. The leaves are indexed in the order of their appearance and the tree is returned together with the labels of the leaves, such that the label of the index `i` is the `i`-th label. Thus, the diagonal element of an index is the depth of its leaf. The labels of the leaves have to be unique, while labels of internal vertices and comments in square brackets are ignored. If the Newick string is malformed, then [`UltrametricError::InvalidNewick`] is returned with the byte position of the error.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let (tree, labels) = ultrametric_matrix_tools::UltrametricTree::from_newick("((A:1,C:1):2,B:3):1;").unwrap();
+    ///
+    /// assert_eq!(labels, vec!["A", "C", "B"]);
+    /// assert_eq!(tree.to_matrix(), ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![4.0, 3.0, 1.0, 3.0, 4.0, 1.0, 1.0, 1.0, 4.0]));
+    /// ```
+    pub fn from_newick(newick: &str) -> Result<(Self, Vec<String>), UltrametricError> {
+        let (nodes, labels) = parse_newick(newick)?;
+        let size = labels.len();
+        let mut levels: Vec<f64> = vec![0.0; nodes.len()];
+        let mut node_builders: Vec<usize> = vec![0; nodes.len()];
+        let mut builders: Vec<VertexBuilder> = Vec::new();
+        for (node_id, node) in nodes.iter().enumerate() {
+            let parent_level = node.parent.map_or(0.0, |parent| levels[parent]);
+            levels[node_id] = parent_level + node.length;
+            match (node.parent, node.leaf_idx) {
+                (Some(parent), Some(leaf_idx)) if node.length == 0.0 => {
+                    builders[node_builders[parent]].leaves.push(leaf_idx);
+                }
+                _ => {
+                    node_builders[node_id] = builders.len();
+                    if let Some(parent) = node.parent {
+                        builders[node_builders[parent]]
+                            .children
+                            .push(node_builders[node_id]);
+                    }
+                    builders.push(VertexBuilder {
+                        level: levels[node_id],
+                        children: Vec::new(),
+                        leaves: node.leaf_idx.into_iter().collect(),
+                    });
+                }
+            }
+        }
+        return Ok((UltrametricTree::from_builders(&builders, 0, size), labels));
+    }
+
+    /// Construct the linkage matrix in the format of `scipy.cluster.hierarchy` that represents the `UltrametricTree`.
+    ///
+    /// The levels are converted to distances by the similarity convention `distance = c - level`, see [`from_linkage`](UltrametricTree::from_linkage). A vertex with more than two children and leaves is split into several merges at the same distance. The merges are sorted by distance, where children are always merged before their parents. The diagonal elements are not part of the linkage matrix.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![4.0, 1.0, 3.0, 1.0, 4.0, 1.0, 3.0, 1.0, 4.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    ///
+    /// assert_eq!(tree.to_linkage(4.0), ultrametric_matrix_tools::na::DMatrix::from_row_slice(2, 4,
+    ///     &[0.0, 2.0, 1.0, 2.0, 1.0, 3.0, 3.0, 3.0]));
+    /// ```
+    pub fn to_linkage(&self, c: f64) -> DMatrix<f64> {
+        let size = self.size();
+        let mut sort_levels: Vec<f64> = self.vertices.iter().map(|vertex| vertex.level).collect();
+        for id in 0..self.vertices.len() {
+            for child in self.children(id) {
+                sort_levels[child] = f64::max(sort_levels[child], sort_levels[id]);
+            }
+        }
+        let mut order: Vec<usize> = (0..self.vertices.len()).rev().collect();
+        order.sort_by(|&id1, &id2| sort_levels[id2].total_cmp(&sort_levels[id1]));
+        let mut merges: Vec<(usize, usize, usize)> = Vec::new();
+        let mut vertex_clusters: Vec<usize> = vec![0; self.vertices.len()];
+        let mut vertex_sizes: Vec<usize> = vec![0; self.vertices.len()];
+        for &id in order.iter() {
+            let mut items: Vec<(usize, usize)> = Vec::new();
+            for child in self.children(id) {
+                items.push((vertex_clusters[child], vertex_sizes[child]));
+            }
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                items.push((leaf_idx, 1));
+            }
+            let (mut cluster, mut cluster_size) = items[0];
+            for &(item, item_size) in items[1..].iter() {
+                merges.push((id, cluster.min(item), cluster.max(item)));
+                cluster_size += item_size;
+                cluster = size + merges.len() - 1;
+            }
+            vertex_clusters[id] = cluster;
+            vertex_sizes[id] = cluster_size;
+        }
+        let mut linkage = DMatrix::<f64>::zeros(merges.len(), 4);
+        let mut cluster_sizes: Vec<usize> = vec![1; size];
+        for (row, &(id, cluster1, cluster2)) in merges.iter().enumerate() {
+            let cluster_size = cluster_sizes[cluster1] + cluster_sizes[cluster2];
+            linkage[(row, 0)] = cluster1 as f64;
+            linkage[(row, 1)] = cluster2 as f64;
+            linkage[(row, 2)] = c - self.vertices[id].level;
+            linkage[(row, 3)] = cluster_size as f64;
+            cluster_sizes.push(cluster_size);
+        }
+        return linkage;
+    }
+
+    /// Construct the Newick string that represents the `UltrametricTree`.
+    ///
+    /// The branch length of each vertex is the difference of its level and the level of its parent, where the branch length of the root is its level, see [`from_newick`](UltrametricTree::from_newick). Thus, the branch length is negative if the level of a vertex is smaller than the level of its parent. The leaf of the index `i` is labeled by `labels[i]`, or by `i` if `labels` is `None`, where labels that contain delimiters or whitespace are quoted. The leaves have the branch length zero, unless their diagonal element differs from the level of their parent. The branch lengths are written with the shortest representation that is parsed to the same value, so [`from_newick`](UltrametricTree::from_newick) reconstructs the levels up to the rounding error of summing the branch lengths. If the number of labels differs from the size of the matrix, then [`UltrametricError::DimensionMismatch`] is returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![4.0, 1.0, 3.0, 1.0, 4.0, 1.0, 3.0, 1.0, 4.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let labels = vec!["A".to_string(), "B".to_string(), "C D".to_string()];
+    ///
+    /// assert_eq!(tree.to_newick(None).unwrap(), "((0:1,2:1):2,1:3):1;");
+    /// assert_eq!(tree.to_newick(Some(&labels)).unwrap(), "((A:1,'C D':1):2,B:3):1;");
+    /// ```
+    pub fn to_newick(&self, labels: Option<&[String]>) -> Result<String, UltrametricError> {
+        if let Some(labels) = labels {
+            if labels.len() != self.size() {
+                return Err(UltrametricError::DimensionMismatch {
+                    expected: self.size(),
+                    found: labels.len(),
+                });
+            }
+        }
+        let label = |leaf_idx: usize| match labels {
+            Some(labels) => quote_label(&labels[leaf_idx]),
+            None => leaf_idx.to_string(),
+        };
+        let weights = self.weights();
+        let mut newick = String::new();
+        let mut stack: Vec<(usize, bool)> = Vec::new();
+        if !self.vertices.is_empty() {
+            stack.push((0, false));
+        }
+        while let Some((id, is_closed)) = stack.pop() {
+            if !is_closed && !newick.is_empty() && !newick.ends_with('(') {
+                newick.push(',');
+            }
+            let leaves = self.partition_leaves(id);
+            if is_closed {
+                for &leaf_idx in leaves.iter() {
+                    if !newick.ends_with('(') {
+                        newick.push(',');
+                    }
+                    newick.push_str(&format!("{}:0", label(leaf_idx)));
+                }
+                newick.push_str(&format!("):{}", weights[id]));
+            } else if leaves.len() == 1 && self.vertices[id].subtree_end == id + 1 {
+                newick.push_str(&format!("{}:{}", label(leaves[0]), weights[id]));
+            } else {
+                newick.push('(');
+                stack.push((id, true));
+                let children: Vec<usize> = self.children(id).collect();
+                for &child in children.iter().rev() {
+                    stack.push((child, false));
+                }
+            }
+        }
+        newick.push(';');
+        return Ok(newick);
+    }
+
+    /// Write the structure of the `UltrametricTree` as text to a writer.
+    ///
+    /// Each vertex is written on one line, where the children of a vertex are indented below it. The content of the lines is controlled by the [`PrintOptions`]. In contrast to [`print_tree`](UltrametricTree::print_tree), errors of the writer are returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let options = ultrametric_matrix_tools::ultrametric_tree::PrintOptions {
+    ///     show_partition: false,
+    ///     show_subtree_size: true,
+    ///     ..Default::default()
+    /// };
+    /// let mut text = Vec::new();
+    /// tree.write_tree(&mut text, &options).unwrap();
+    ///
+    /// assert_eq!(String::from_utf8(text).unwrap().lines().nth(1), Some("├─ [], 3, 2 indices"));
+    /// ```
+    pub fn write_tree<W: Write>(&self, writer: W, options: &PrintOptions) -> io::Result<()> {
+        if self.vertices.is_empty() {
+            return Ok(());
+        }
+        return write_tree(&self.build_print_tree(options), writer);
+    }
+
+    /// Construct the tree of labels that is written by [`write_tree`](UltrametricTree::write_tree) and the `Display` and `Debug` implementations.
+    ///
+    /// The tree is traversed in pre-order with an explicit stack, where `None` marks the end of the children of a vertex. The indices of the partitions are shown in ascending order.
+    fn build_print_tree(&self, options: &PrintOptions) -> StringItem {
+        let format_indices = |indices: &[usize]| {
+            let mut indices = indices.to_vec();
+            indices.sort_unstable();
+            return match options.max_indices {
+                Some(max_indices) if indices.len() > max_indices => {
+                    let shown: Vec<String> = indices[..max_indices]
+                        .iter()
+                        .map(|idx| idx.to_string())
+                        .collect();
+                    let mut list = shown.join(", ");
+                    if max_indices > 0 {
+                        list.push_str(", ");
+                    }
+                    format!("[{}... {} more]", list, indices.len() - max_indices)
+                }
+                _ => format!("{:?}", indices),
+            };
+        };
+        let label = |id: usize| {
+            let mut parts: Vec<String> = Vec::new();
+            if options.show_partition {
+                parts.push(format_indices(self.partition(id)));
+            }
+            parts.push(format_indices(self.partition_leaves(id)));
+            parts.push(match options.precision {
+                Some(precision) => format!("{:.*}", precision, self.vertices[id].level),
+                None => format!("{}", self.vertices[id].level),
+            });
+            if options.show_subtree_size {
+                parts.push(format!("{} indices", self.partition(id).len()));
+            }
+            return parts.join(", ");
+        };
+
+        let mut tree_root = TreeBuilder::new(label(0));
+        let mut stack: Vec<Option<usize>> = self.children(0).map(Some).collect();
+        stack.reverse();
+        while let Some(vertex_id) = stack.pop() {
+            let id = match vertex_id {
+                Some(id) => id,
+                None => {
+                    tree_root.end_child();
+                    continue;
+                }
+            };
+            if id + 1 == self.vertices[id].subtree_end {
+                tree_root.add_empty_child(label(id));
+                continue;
+            }
+            tree_root.begin_child(label(id));
+            stack.push(None);
+            let first_child = stack.len();
+            stack.extend(self.children(id).map(Some));
+            stack[first_child..].reverse();
+        }
+        return tree_root.build();
+    }
+
+    /// Construct a graph in the DOT language of Graphviz that represents the `UltrametricTree`.
+    ///
+    /// Each vertex is a node with the name `v{id}`, where `id` is the position of the vertex in pre-order, and it is connected to its children by directed edges. The label of a node contains the `level`, the size of the `partition` and the `partition_leaves` of the vertex. The labels of large partitions and the colors of the nodes are controlled by the [`DotOptions`]. The graph can be rendered with `dot -Tsvg`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(3, 3,
+    ///     vec![4.0, 1.0, 1.0, 1.0, 4.0, 3.0, 1.0, 3.0, 3.0]);
+    /// let mut tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// tree.prune_tree();
+    /// let options = ultrametric_matrix_tools::ultrametric_tree::DotOptions {
+    ///     max_partition_size: Some(1),
+    ///     color_by_level: false,
+    /// };
+    /// let dot = tree.to_dot(&options);
+    ///
+    /// assert!(dot.contains("v2 [label=\"level: 3\\nsize: 2\\nleaves: 1 collapsed\"];"));
+    /// assert!(dot.contains("v2 -> v3;"));
+    /// ```
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        let mut min_level = f64::INFINITY;
+        let mut max_level = f64::NEG_INFINITY;
+        for vertex in self.vertices.iter() {
+            if vertex.level.is_finite() {
+                min_level = f64::min(min_level, vertex.level);
+                max_level = f64::max(max_level, vertex.level);
+            }
+        }
+        let mut dot = String::from("digraph UltrametricTree {\n    node [shape=box];\n");
+        for (id, vertex) in self.vertices.iter().enumerate() {
+            let partition_size = vertex.partition_end - vertex.partition_start;
+            let leaves = self.partition_leaves(id);
+            let leaves_label = match options.max_partition_size {
+                Some(max_size) if partition_size > max_size && !leaves.is_empty() => {
+                    format!("{} collapsed", leaves.len())
+                }
+                _ => format!("{:?}", leaves),
+            };
+            dot.push_str(&format!(
+                "    v{} [label=\"level: {}\\nsize: {}\\nleaves: {}\"",
+                id, vertex.level, partition_size, leaves_label
+            ));
+            if options.color_by_level {
+                let fraction = if max_level > min_level {
+                    ((vertex.level - min_level) / (max_level - min_level)).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                dot.push_str(&format!(
+                    ", style=filled, fillcolor=\"{:.3} 0.400 1.000\"",
+                    0.667 * (1.0 - fraction)
+                ));
+            }
+            dot.push_str("];\n");
+        }
+        for id in 0..self.vertices.len() {
+            for child in self.children(id) {
+                dot.push_str(&format!("    v{} -> v{};\n", id, child));
+            }
+        }
+        dot.push_str("}\n");
+        return dot;
+    }
+
+    /// Draw the `UltrametricTree` as dendrogram in the SVG format.
+    ///
+    /// The indices are placed on the leaf axis in the order of the permutation of the tree, see [`get_permutation_matrix`](UltrametricTree::get_permutation_matrix), such that no lines cross. The distance of a vertex from the root side is given by its `level`, where the lowest level is on the side of the root and the highest level is on the side of the leaves. Each vertex is drawn as a line at its level that connects its children and its `partition_leaves`, and each child is connected to this line. The labels, the orientation and the size of the image are controlled by the [`SvgOptions`]. The image is written directly, so no external renderer is needed.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let options = ultrametric_matrix_tools::ultrametric_tree::SvgOptions {
+    ///     labels: Some(vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()]),
+    ///     ..Default::default()
+    /// };
+    /// let svg = tree.to_svg(&options);
+    ///
+    /// assert!(svg.starts_with("<svg"));
+    /// assert!(svg.contains(">c</text>"));
+    /// ```
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        let size = self.size();
+        let labels: Vec<String> = (0..size)
+            .map(|idx| {
+                let label = match &options.labels {
+                    Some(labels) if idx < labels.len() => labels[idx].clone(),
+                    _ => idx.to_string(),
+                };
+                return label
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+                    .replace('"', "&quot;");
+            })
+            .collect();
+        let label_space = if options.show_labels {
+            let max_len = labels.iter().map(|label| label.chars().count()).max();
+            options.font_size * (0.6 * max_len.unwrap_or(0) as f64 + 1.0)
+        } else {
+            0.0
+        };
+        let is_vertical = matches!(
+            options.orientation,
+            DendrogramOrientation::Top | DendrogramOrientation::Bottom
+        );
+        let (leaf_length, level_length) = if is_vertical {
+            (options.width, options.height)
+        } else {
+            (options.height, options.width)
+        };
+        let leaf_length = f64::max(leaf_length - 2.0 * SVG_MARGIN, 0.0);
+        let level_length = f64::max(level_length - 2.0 * SVG_MARGIN - label_space, 0.0);
+        let point = |leaf_coord: f64, level_coord: f64| {
+            let leaf_pos = SVG_MARGIN + leaf_coord * leaf_length;
+            let level_pos = level_coord * level_length;
+            return match options.orientation {
+                DendrogramOrientation::Top => (leaf_pos, SVG_MARGIN + level_pos),
+                DendrogramOrientation::Bottom => {
+                    (leaf_pos, options.height - SVG_MARGIN - level_pos)
+                }
+                DendrogramOrientation::Left => (SVG_MARGIN + level_pos, leaf_pos),
+                DendrogramOrientation::Right => (options.width - SVG_MARGIN - level_pos, leaf_pos),
+            };
+        };
+
+        let mut min_level = f64::INFINITY;
+        let mut max_level = f64::NEG_INFINITY;
+        for vertex in self.vertices.iter() {
+            if vertex.level.is_finite() {
+                min_level = f64::min(min_level, vertex.level);
+                max_level = f64::max(max_level, vertex.level);
+            }
+        }
+        let level_coords: Vec<f64> = self
+            .vertices
+            .iter()
+            .map(|vertex| {
+                if max_level > min_level {
+                    return ((vertex.level - min_level) / (max_level - min_level)).clamp(0.0, 1.0);
+                }
+                return 0.0;
+            })
+            .collect();
+        let mut leaf_coords: Vec<f64> = vec![0.0; size];
+        for (position, &idx) in self.permutation.iter().enumerate() {
+            leaf_coords[idx] = (position as f64 + 0.5) / size as f64;
+        }
+        let mut vertex_coords: Vec<f64> = vec![0.0; self.vertices.len()];
+        for id in (0..self.vertices.len()).rev() {
+            let mut sum = 0.0;
+            let mut count = 0;
+            for child in self.children(id) {
+                sum += vertex_coords[child];
+                count += 1;
+            }
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                sum += leaf_coords[leaf_idx];
+                count += 1;
+            }
+            vertex_coords[id] = if count > 0 { sum / count as f64 } else { 0.5 };
+        }
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = options.width,
+            h = options.height
+        );
+        svg.push_str("  <g stroke=\"black\" stroke-width=\"1\" fill=\"none\">\n");
+        let mut push_line = |from: (f64, f64), to: (f64, f64)| {
+            svg.push_str(&format!(
+                "    <line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\"/>\n",
+                from.0, from.1, to.0, to.1
+            ));
+        };
+        for id in 0..self.vertices.len() {
+            let mut first_coord = f64::INFINITY;
+            let mut last_coord = f64::NEG_INFINITY;
+            for child in self.children(id) {
+                first_coord = f64::min(first_coord, vertex_coords[child]);
+                last_coord = f64::max(last_coord, vertex_coords[child]);
+                push_line(
+                    point(vertex_coords[child], level_coords[id]),
+                    point(vertex_coords[child], level_coords[child]),
+                );
+            }
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                first_coord = f64::min(first_coord, leaf_coords[leaf_idx]);
+                last_coord = f64::max(last_coord, leaf_coords[leaf_idx]);
+            }
+            if first_coord < last_coord {
+                push_line(
+                    point(first_coord, level_coords[id]),
+                    point(last_coord, level_coords[id]),
+                );
+            }
+        }
+        svg.push_str("  </g>\n");
+
+        if options.show_labels {
+            svg.push_str(&format!(
+                "  <g font-family=\"sans-serif\" font-size=\"{}\">\n",
+                options.font_size
+            ));
+            for (idx, label) in labels.iter().enumerate() {
+                let (x, y) = point(leaf_coords[idx], 1.0);
+                let (x, y, anchor, rotation) = match options.orientation {
+                    DendrogramOrientation::Top => (x, y + 0.5 * options.font_size, "end", -90),
+                    DendrogramOrientation::Bottom => (x, y - 0.5 * options.font_size, "start", -90),
+                    DendrogramOrientation::Left => (x + 0.5 * options.font_size, y, "start", 0),
+                    DendrogramOrientation::Right => (x - 0.5 * options.font_size, y, "end", 0),
+                };
+                svg.push_str(&format!(
+                    "    <text x=\"{x:.2}\" y=\"{y:.2}\" text-anchor=\"{}\" dominant-baseline=\"middle\" transform=\"rotate({} {x:.2} {y:.2})\">{}</text>\n",
+                    anchor,
+                    rotation,
+                    label,
+                    x = x,
+                    y = y
+                ));
+            }
+            svg.push_str("  </g>\n");
+        }
+        svg.push_str("</svg>\n");
+        return svg;
+    }
+
+    /// Write the `UltrametricTree` in the compact binary format, see the [`binary`](crate::binary) module.
+    ///
+    /// The tree can be loaded without deserialization by [`MappedUltrametricTree::open`](crate::binary::MappedUltrametricTree::open) or [`UltrametricTreeView::from_bytes`](crate::binary::UltrametricTreeView::from_bytes). Since every number is written separately, the writer should be buffered.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let mut bytes = Vec::new();
+    /// tree.write_binary(&mut bytes).unwrap();
+    ///
+    /// assert_eq!(&bytes[0..8], &ultrametric_matrix_tools::binary::MAGIC);
+    /// ```
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&BINARY_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&(self.permutation.len() as u64).to_le_bytes())?;
+        writer.write_all(&(self.vertices.len() as u64).to_le_bytes())?;
+        for vertex in self.vertices.iter() {
+            writer.write_all(&vertex.level.to_le_bytes())?;
+        }
+        for vertex in self.vertices.iter() {
+            writer.write_all(&(vertex.subtree_end as u64).to_le_bytes())?;
+        }
+        for vertex in self.vertices.iter() {
+            writer.write_all(&(vertex.leaf_start as u64).to_le_bytes())?;
+        }
+        for vertex in self.vertices.iter() {
+            writer.write_all(&(vertex.partition_end as u64).to_le_bytes())?;
+        }
+        for &idx in self.permutation.iter() {
+            writer.write_all(&(idx as u64).to_le_bytes())?;
+        }
+        return Ok(());
+    }
+
+    /// Calculate the product of an ultrametric matrix represented by an `UltrametricTree` and a vector.
+    ///
+    /// The multiplication is done in two steps. The first step calculates the partial product of each vertex, which is stored in a temporary vector. The second step sums the partial products to the full product for each element of the product vector. Since the `UltrametricTree` is not modified, it can be shared between threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size of `vector` differs from the size of the matrix.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let vector = ultrametric_matrix_tools::na::DVector::from_vec(vec![4.0, 2.0, 7.0, 5.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let product = tree.mult(&vector);
+    ///
+    /// assert_eq!(ultrametric_matrix_tools::na::DVector::from_vec(vec![28.0, 22.0, 54.0, 18.0]),
+    ///     product);
+    /// ```
+    pub fn mult(&self, vector: &DVector<f64>) -> DVector<f64> {
+        let mut product: DVector<f64> = DVector::<f64>::zeros(vector.nrows());
+        self.mult_into(vector, &mut product, &mut MultWorkspace::default());
+        return product;
+    }
+
+    /// Calculate the product of an ultrametric matrix represented by an `UltrametricTree` and a vector and store it in `out`.
+    ///
+    /// The multiplication is the same as in [`mult`](UltrametricTree::mult), but the partial products are stored in the [`MultWorkspace`]. Thus, no memory is allocated once the workspace has been used with a tree of the same size, except for the parallel tasks with the `rayon` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size of `vector` or `out` differs from the size of the matrix.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let vector = ultrametric_matrix_tools::na::DVector::from_vec(vec![4.0, 2.0, 7.0, 5.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let mut workspace = ultrametric_matrix_tools::ultrametric_tree::MultWorkspace::default();
+    /// let mut product = ultrametric_matrix_tools::na::DVector::zeros(4);
+    /// tree.mult_into(&vector, &mut product, &mut workspace);
+    ///
+    /// assert_eq!(ultrametric_matrix_tools::na::DVector::from_vec(vec![28.0, 22.0, 54.0, 18.0]),
+    ///     product);
+    /// ```
+    pub fn mult_into(
+        &self,
+        vector: &DVector<f64>,
+        out: &mut DVector<f64>,
+        workspace: &mut MultWorkspace,
+    ) {
+        self.gemv_into(1.0, vector, 0.0, out, workspace);
+    }
+
+    /// Calculate `out = alpha * A * vector + beta * out`, where `A` is the ultrametric matrix represented by the `UltrametricTree`.
+    ///
+    /// This is the generalization of [`mult_into`](UltrametricTree::mult_into) in the style of `gemv` of BLAS, which also does not allocate memory. If `beta` is zero, then `out` does not need to be initialized, i.e. NaN in `out` is overwritten.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size of `vector` or `out` differs from the size of the matrix.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let vector = ultrametric_matrix_tools::na::DVector::from_vec(vec![4.0, 2.0, 7.0, 5.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let mut workspace = ultrametric_matrix_tools::ultrametric_tree::MultWorkspace::default();
+    /// let mut out = ultrametric_matrix_tools::na::DVector::from_vec(vec![1.0, 1.0, 1.0, 1.0]);
+    /// tree.gemv_into(2.0, &vector, -1.0, &mut out, &mut workspace);
+    ///
+    /// assert_eq!(ultrametric_matrix_tools::na::DVector::from_vec(vec![55.0, 43.0, 107.0, 35.0]),
+    ///     out);
+    /// ```
+    pub fn gemv_into(
+        &self,
+        alpha: f64,
+        vector: &DVector<f64>,
+        beta: f64,
+        out: &mut DVector<f64>,
+        workspace: &mut MultWorkspace,
+    ) {
+        let size = self.size();
+        assert!(
+            vector.nrows() == size && out.nrows() == size,
+            "dimension mismatch: expected size {}, found vector of size {} and output of size {}",
+            size,
+            vector.nrows(),
+            out.nrows()
+        );
+        self.calculate_partial_product(vector, &mut workspace.sums);
+        self.calculate_full_product(alpha, beta, out, &mut workspace.sums);
+    }
+
+    /// Calculate the product of an ultrametric matrix represented by an `UltrametricTree` and a matrix.
+    ///
+    /// All columns of `matrix` are multiplied in a single traversal of the tree in the same two steps as in [`mult`](UltrametricTree::mult). The partial sums of each vertex are stored as one contiguous block with one element per column, so the sums of a child are added to its parent block by block. For this, `matrix` is transposed into row-major order once, which also makes the indices of each leaf contiguous.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of rows of `matrix` differs from the size of the matrix.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let other = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 2,
+    ///     vec![4.0, 2.0, 7.0, 5.0, 1.0, 0.0, 0.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let product = tree.mult_matrix(&other);
+    ///
+    /// assert_eq!(&matrix * &other, product);
+    /// ```
+    pub fn mult_matrix(&self, matrix: &DMatrix<f64>) -> DMatrix<f64> {
+        let num_rows = matrix.nrows();
+        assert!(
+            num_rows == self.size(),
+            "dimension mismatch: expected size {}, found matrix with {} rows",
+            self.size(),
+            num_rows
+        );
+        let num_columns = matrix.ncols();
+        let block = |id: usize| id * num_columns..(id + 1) * num_columns;
+        let transposed = matrix.transpose();
+        let rows = transposed.as_slice();
+        let mut sums: Vec<f64> = vec![0.; self.vertices.len() * num_columns];
+        for id in (0..self.vertices.len()).rev() {
+            let (head, tail) = sums.split_at_mut((id + 1) * num_columns);
+            let sum = &mut head[block(id)];
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                for (value, &row_value) in sum.iter_mut().zip(rows[block(leaf_idx)].iter()) {
+                    *value += row_value;
+                }
+            }
+            for child in self.children(id) {
+                let child_sum = &tail[block(child - id - 1)];
+                for (value, &child_value) in sum.iter_mut().zip(child_sum.iter()) {
+                    *value += child_value;
+                }
+            }
+        }
+        let weights = self.weights();
+        for (id, &weight) in weights.iter().enumerate() {
+            for value in sums[block(id)].iter_mut() {
+                *value *= weight;
+            }
+        }
+
+        let mut product_rows: Vec<f64> = vec![0.; num_rows * num_columns];
+        for id in 0..self.vertices.len() {
+            let (head, tail) = sums.split_at_mut((id + 1) * num_columns);
+            let sum = &head[block(id)];
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                product_rows[block(leaf_idx)].copy_from_slice(sum);
+            }
+            for child in self.children(id) {
+                for (value, &parent_value) in tail[block(child - id - 1)].iter_mut().zip(sum.iter())
+                {
+                    *value += parent_value;
+                }
+            }
+        }
+        return DMatrix::from_row_slice(num_rows, num_columns, &product_rows);
+    }
+
+    /// Calculate the partial products bottom-up in `sums`, which is the first step to calculate the product in [`mult`](UltrametricTree::mult).
+    ///
+    /// The length of `sums` is adjusted to the number of vertices, which only allocates memory if its capacity is too small. With the `rayon` feature, the subtrees of [`parallel_subtrees`](UltrametricTree::parallel_subtrees) are processed in parallel before the remaining vertices.
+    fn calculate_partial_product(&self, vector: &DVector<f64>, sums: &mut Vec<f64>) {
+        sums.clear();
+        sums.resize(self.vertices.len(), 0.);
+        if sums.is_empty() {
+            return;
+        }
+        #[cfg(not(feature = "rayon"))]
+        self.partial_product_subtree(vector, 0, sums);
+        #[cfg(feature = "rayon")]
+        {
+            let (sequential_ranges, roots) = self.parallel_subtrees();
+            self.split_sums(&roots, sums)
+                .into_par_iter()
+                .for_each(|(root, subtree_sums)| {
+                    self.partial_product_subtree(vector, root, subtree_sums);
+                });
+            for range in sequential_ranges.into_iter().rev() {
+                for id in range.rev() {
+                    self.partial_product_vertex(vector, id, 0, sums);
+                }
+            }
+        }
+        sums[0] *= self.vertices[0].level;
+    }
+
+    /// Calculate the partial products of the subtree of the vertex `root`, where `sums` starts at the vertex `root`.
+    ///
+    /// Since the vertices are stored in pre-order, the children of a vertex are visited before the vertex when iterating in reverse order.
+    fn partial_product_subtree(&self, vector: &DVector<f64>, root: usize, sums: &mut [f64]) {
+        for id in (root..self.vertices[root].subtree_end).rev() {
+            self.partial_product_vertex(vector, id, root, sums);
+        }
+    }
+
+    /// Calculate the partial product of the vertex `id` from the partial products of its children, where `sums` starts at the vertex `offset`.
+    ///
+    /// The sum of a child is multiplied by its weight after it is added to the sum of its parent.
+    fn partial_product_vertex(
+        &self,
+        vector: &DVector<f64>,
+        id: usize,
+        offset: usize,
+        sums: &mut [f64],
+    ) {
+        let level = self.vertices[id].level;
+        let mut sum = 0.;
+        for &leaf_idx in self.partition_leaves(id).iter() {
+            sum += vector[leaf_idx];
+        }
+        for child in self.children(id) {
+            sum += sums[child - offset];
+            sums[child - offset] *= self.vertices[child].level - level;
+        }
+        sums[id - offset] = sum;
+    }
+
+    /// Sum the partial products top-down in place, which is the second step to calculate the product in [`mult`](UltrametricTree::mult), and combine them with `product` as in [`gemv_into`](UltrametricTree::gemv_into).
+    ///
+    /// With the `rayon` feature, the subtrees of [`split_sums`](UltrametricTree::split_sums) are processed in parallel after the remaining vertices.
+    fn calculate_full_product(
+        &self,
+        alpha: f64,
+        beta: f64,
+        product: &mut DVector<f64>,
+        sums: &mut [f64],
+    ) {
+        if sums.is_empty() {
+            return;
+        }
+        #[cfg(not(feature = "rayon"))]
+        self.full_product_subtree(0, sums);
+        #[cfg(feature = "rayon")]
+        {
+            let (sequential_ranges, roots) = self.parallel_subtrees();
+            for id in sequential_ranges.into_iter().flatten() {
+                self.full_product_vertex(id, 0, sums);
+            }
+            self.split_sums(&roots, sums)
+                .into_par_iter()
+                .for_each(|(root, subtree_sums)| {
+                    self.full_product_subtree(root, subtree_sums);
+                });
+        }
+
+        let combine = |(leaf_idx, product_i): (usize, &mut f64)| {
+            let sum = sums[self.leaf_vertices[leaf_idx]];
+            *product_i = if beta == 0. {
+                alpha * sum
+            } else {
+                alpha * sum + beta * *product_i
+            };
+        };
+        #[cfg(not(feature = "rayon"))]
+        product.iter_mut().enumerate().for_each(combine);
+        #[cfg(feature = "rayon")]
+        product
+            .as_mut_slice()
+            .par_iter_mut()
+            .with_min_len(PARALLEL_MIN_SIZE)
+            .enumerate()
+            .for_each(combine);
+    }
+
+    /// Sum the partial products of the subtree of the vertex `root` top-down, where `sums` starts at the vertex `root`.
+    fn full_product_subtree(&self, root: usize, sums: &mut [f64]) {
+        for id in root..self.vertices[root].subtree_end {
+            self.full_product_vertex(id, root, sums);
+        }
+    }
+
+    /// Add the full product of the vertex `id` to the partial products of its children, where `sums` starts at the vertex `offset`.
+    fn full_product_vertex(&self, id: usize, offset: usize, sums: &mut [f64]) {
+        let sum = sums[id - offset];
+        for child in self.children(id) {
+            sums[child - offset] += sum;
+        }
+    }
+
+    /// Split the vertices into the ranges of vertices that are processed sequentially and the roots of the subtrees that are processed in parallel, which are independent of each other.
+    ///
+    /// The subtrees are the maximal subtrees with less than [`PARALLEL_MIN_SIZE`] indices in their `partition`. Subtrees with less than [`PARALLEL_MIN_TASK_SIZE`] indices are too small for their own task and their vertices are processed sequentially. Both vectors are in pre-order. Since the partitions of the ancestors of a vertex contain its `partition`, the sequential vertices are above or beside the parallel subtrees.
+    #[cfg(feature = "rayon")]
+    fn parallel_subtrees(&self) -> (Vec<ops::Range<usize>>, Vec<usize>) {
+        let mut sequential_ranges: Vec<ops::Range<usize>> = Vec::new();
+        let mut roots: Vec<usize> = Vec::new();
+        let mut sequential_start = 0;
+        let mut id = 0;
+        while id < self.vertices.len() {
+            let vertex = &self.vertices[id];
+            let size = vertex.partition_end - vertex.partition_start;
+            if size >= PARALLEL_MIN_SIZE {
+                id += 1;
+                continue;
+            }
+            if size >= PARALLEL_MIN_TASK_SIZE {
+                if sequential_start < id {
+                    sequential_ranges.push(sequential_start..id);
+                }
+                roots.push(id);
+                sequential_start = vertex.subtree_end;
+            }
+            id = vertex.subtree_end;
+        }
+        if sequential_start < self.vertices.len() {
+            sequential_ranges.push(sequential_start..self.vertices.len());
+        }
+        return (sequential_ranges, roots);
+    }
+
+    /// Split `sums` into the disjoint slices of the subtrees of the vertices `roots`, which have to be in pre-order.
+    #[cfg(feature = "rayon")]
+    fn split_sums<'a>(&self, roots: &[usize], sums: &'a mut [f64]) -> Vec<(usize, &'a mut [f64])> {
+        let mut subtrees: Vec<(usize, &'a mut [f64])> = Vec::with_capacity(roots.len());
+        let mut rest = sums;
+        let mut offset = 0;
+        for &root in roots.iter() {
+            let subtree_end = self.vertices[root].subtree_end;
+            let (_, subtree_sums) = std::mem::take(&mut rest).split_at_mut(root - offset);
+            let (subtree_sums, next_rest) = subtree_sums.split_at_mut(subtree_end - root);
+            subtrees.push((root, subtree_sums));
+            rest = next_rest;
+            offset = subtree_end;
+        }
+        return subtrees;
+    }
+
+    /// Solve the equation system `A x = b`, where `A` is the ultrametric matrix represented by the `UltrametricTree`.
+    ///
+    /// The equation system is solved directly on the tree in linear time by multiplying `b` with the [`inverse`](UltrametricTree::inverse) of the matrix. If the matrix is singular, then [`UltrametricError::SingularMatrix`] is returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
+    /// let b = ultrametric_matrix_tools::na::DVector::from_vec(vec![3.0, 2.0, 6.0, 7.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let x = tree.solve(&b).unwrap();
+    ///
+    /// assert!((&matrix * x - b).norm() < 1e-12);
+    /// ```
+    pub fn solve(&self, b: &DVector<f64>) -> Result<DVector<f64>, UltrametricError> {
+        let size = self.size();
+        if b.nrows() != size {
+            return Err(UltrametricError::DimensionMismatch {
+                expected: size,
+                found: b.nrows(),
+            });
+        }
+        let inverse = self.inverse()?;
+        return inverse.mult(b);
+    }
+
+    /// Construct the structured inverse of the ultrametric matrix represented by the `UltrametricTree`.
+    ///
+    /// The inverse is computed in linear time by eliminating the tree bottom-up, such that each vertex is annotated with a linear relation between the sum of the solution over its `partition` and the partial product of its ancestors. The resulting [`UltrametricInverse`] can be multiplied with a vector in linear time and written out as dense matrix or in CSR format. If the matrix is singular, then [`UltrametricError::SingularMatrix`] is returned.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let inverse = tree.inverse().unwrap().to_matrix();
+    ///
+    /// assert!((matrix.try_inverse().unwrap() - inverse).norm() < 1e-12);
+    /// ```
+    pub fn inverse(&self) -> Result<UltrametricInverse, UltrametricError> {
+        let mut inverse = UltrametricInverse {
+            vertices: vec![InverseVertex::default(); self.vertices.len()],
+            size: self.size(),
+        };
+        let weights = self.weights();
+        for id in (0..self.vertices.len()).rev() {
+            self.eliminate_vertex(id, weights[id], &mut inverse.vertices)?;
+        }
+        if inverse.vertices[0].a.abs() <= SINGULAR_TOLERANCE {
+            return Err(UltrametricError::SingularMatrix);
+        }
+        return Ok(inverse);
     }
-}
 
-impl UltrametricTree {
-    /// Create a new vertex using `partition`.
-    fn new(partition: Vec<usize>) -> Self {
-        UltrametricTree {
-            partition: partition,
-            ..Default::default()
+    /// Eliminate the vertex `id`, whose children are already eliminated, which is used to construct the [`inverse`](UltrametricTree::inverse).
+    ///
+    /// The vertices are stored in pre-order in `vertices` and eliminated in reverse order. A leaf of the vertex fixes the partial product of the vertex, which is the relation `0 * sum + 1 * prev_sum = d`. The child or leaf with the smallest `|a|` is chosen as pivot, whose sum is determined last from the sum of the vertex. The sums of all other children are expressed by the partial product of the vertex. If more than one child or leaf has `a = 0`, then the matrix is singular.
+    fn eliminate_vertex(
+        &self,
+        id: usize,
+        weight: f64,
+        vertices: &mut [InverseVertex],
+    ) -> Result<(), UltrametricError> {
+        let partition_leaves = self.partition_leaves(id);
+        if partition_leaves.len() > 1 {
+            return Err(UltrametricError::SingularMatrix);
+        }
+        let child_ids: Vec<usize> = self.children(id).collect();
+
+        let mut pivot_id = None;
+        let mut pivot_a = f64::MAX;
+        let mut pivot_c = 0.;
+        if !partition_leaves.is_empty() {
+            pivot_a = 0.;
+            pivot_c = 1.;
+        }
+        for &child_id in child_ids.iter() {
+            if vertices[child_id].a.abs() < pivot_a.abs() {
+                pivot_id = Some(child_id);
+                pivot_a = vertices[child_id].a;
+                pivot_c = vertices[child_id].c;
+            }
+        }
+        let mut sum_c = 0.;
+        for &child_id in child_ids.iter() {
+            if Some(child_id) != pivot_id {
+                if vertices[child_id].a.abs() <= SINGULAR_TOLERANCE {
+                    return Err(UltrametricError::SingularMatrix);
+                }
+                sum_c += vertices[child_id].c / vertices[child_id].a;
+            }
+        }
+
+        let c = pivot_a * sum_c + pivot_c;
+        let a = pivot_a + c * weight;
+        let norm = f64::max(a.abs(), c.abs());
+        if norm == 0.0 {
+            return Err(UltrametricError::SingularMatrix);
+        }
+        for &child_id in child_ids.iter() {
+            let child_vertex = &mut vertices[child_id];
+            if Some(child_id) == pivot_id {
+                child_vertex.is_pivot = true;
+                child_vertex.d_factor = 1. / norm;
+            } else {
+                child_vertex.d_factor = pivot_a / (child_vertex.a * norm);
+            }
+        }
+        let size = self.vertices[id].subtree_end - id;
+        let vertex = &mut vertices[id];
+        vertex.weight = weight;
+        vertex.a = a / norm;
+        vertex.c = c / norm;
+        vertex.size = size;
+        if pivot_id.is_none() {
+            vertex.pivot_leaf = partition_leaves.first().copied();
+            vertex.leaf_factor = 1. / norm;
         }
+        return Ok(());
     }
 
-    /// Construct a `UltrametricTree` from an ultrametric matrix that represents the structure of the matrix.
+    /// Calculate the determinant of the ultrametric matrix represented by the `UltrametricTree`.
     ///
-    /// This function does not check if the matrix is ultrametric. The value retured by this function is the root of the tree that represents the ultrametric matrix `matrix`. Thus, the function returns the tree that represents `matrix`.
+    /// The determinant is calculated in linear time by [`log_det`](UltrametricTree::log_det). Thus, the determinant might overflow for large matrices, in which case [`log_det`](UltrametricTree::log_det) should be used instead.
     ///
     /// # Example:
     ///
     /// ```
     /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
-    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
     /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    ///
+    /// assert!((tree.det() - matrix.determinant()).abs() < 1e-9);
     /// ```
-    pub fn from_matrix(matrix: &DMatrix<f64>) -> Self {
-        let vertex_ids: Vec<usize> = (0..matrix.nrows()).collect();
-        let mut root = UltrametricTree::new(vertex_ids);
-        root.from_matrix_recursive(&matrix);
-        return root;
-    }
-
-    /// Recursive function used to construct the tree from an ultrametric matrix.
-    fn from_matrix_recursive(&mut self, matrix: &DMatrix<f64>) {
-        let first_i = self.partition[0];
-        if self.partition.len() == 1 {
-            self.level = matrix[(first_i, first_i)];
-            self.partition_leaves.push(first_i);
-        } else {
-            let mut left_partition: Vec<usize> = vec![first_i];
-            let mut right_partition: Vec<usize> = Vec::new();
-            let mut min = f64::MAX;
-            for &i in &self.partition[1..] {
-                if min > matrix[(first_i, i)] {
-                    min = matrix[(first_i, i)];
-                    left_partition.extend(right_partition.iter());
-                    right_partition.clear();
-                    right_partition.push(i);
-                } else {
-                    if min == matrix[(first_i, i)] {
-                        right_partition.push(i);
-                    } else {
-                        left_partition.push(i);
-                    }
-                }
-            }
-            self.level = min;
-            let left_child = Box::new(UltrametricTree::new(left_partition));
-            let right_child = Box::new(UltrametricTree::new(right_partition));
-            self.children.push(left_child);
-            self.children.push(right_child);
-
-            self.children[0].as_mut().from_matrix_recursive(matrix);
-            self.children[1].as_mut().from_matrix_recursive(matrix);
-        }
+    pub fn det(&self) -> f64 {
+        let (sign, log_abs_det) = self.log_det();
+        return sign * log_abs_det.exp();
     }
 
-    /// Construct a `UltrametricTree` from an ultrametric matrix that approximately represents the structure of the matrix.
+    /// Calculate the sign and the natural logarithm of the absolute value of the determinant of the ultrametric matrix represented by the `UltrametricTree`.
     ///
-    /// This function is similar to [`from_matrix`](UltrametricTree::from_matrix). The difference is that the elements associated with the indices in the right_partition are at most `min + eps` instead of equal to `min`. Only the minimal value is stored in the vertex.
+    /// The determinant is calculated recursively using the matrix determinant lemma. The submatrix of a vertex is the sum of a block diagonal matrix `D`, whose blocks are the submatrices of the children and zeros for the leaves, and the rank-one matrix `(level - parent_level) * 1 * 1^T`. Thus, the determinant of the submatrix is `det(D) + (level - parent_level) * 1^T adj(D) 1`. Both `det(D)` and `1^T adj(D) 1` are tracked on a common logarithmic scale to avoid overflows. If the matrix is singular, then the sign is `0` and the logarithm is negative infinity.
     ///
     /// # Example:
     ///
     /// ```
     /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
-    ///     vec![0.0, 1.1, 3.0, 1.0, 1.1, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
-    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix_approx(&matrix, 0.1);
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let (sign, log_abs_det) = tree.log_det();
+    ///
+    /// assert_eq!(sign, 1.0);
+    /// assert!((log_abs_det - matrix.determinant().ln()).abs() < 1e-12);
     /// ```
-    pub fn from_matrix_approx(matrix: &DMatrix<f64>, eps: f64) -> Self {
-        let vertex_ids: Vec<usize> = (0..matrix.nrows()).collect();
-        let mut root = UltrametricTree::new(vertex_ids);
-        root.from_matrix_approx_recursive(&matrix, eps);
-        return root;
-    }
-
-    /// Recursive function used to construct the tree that approximately represents the structure of an ultrametric matrix.
-    fn from_matrix_approx_recursive(&mut self, matrix: &DMatrix<f64>, eps: f64) {
-        let first_i = self.partition[0];
-        if self.partition.len() == 1 {
-            self.level = matrix[(first_i, first_i)];
-            self.partition_leaves.push(first_i);
-        } else {
-            let mut left_partition: Vec<usize> = vec![first_i];
-            let mut right_partition: Vec<usize> = Vec::new();
-            let mut min = f64::MAX;
+    pub fn log_det(&self) -> (f64, f64) {
+        if self.vertices.is_empty() {
+            return (1.0, 0.0);
+        }
+        let (det, _, log_scale) = self.log_det_vertices()[0];
+        if det == 0.0 {
+            return (0.0, f64::NEG_INFINITY);
+        }
+        return (det.signum(), det.abs().ln() + log_scale);
+    }
 
-            for &i in &self.partition[1..] {
-                if matrix[(first_i, i)] < min {
-                    min = matrix[(first_i, i)];
-                }
+    /// Calculate the determinants in [`log_det`](UltrametricTree::log_det) bottom-up for all vertices.
+    ///
+    /// The values `(det, adj_sum, log_scale)` of a vertex represent the determinant `det * exp(log_scale)` and the sum of the adjugate `adj_sum * exp(log_scale)` of the submatrix of the vertex minus the level of its parent.
+    fn log_det_vertices(&self) -> Vec<(f64, f64, f64)> {
+        let weights = self.weights();
+        let mut results: Vec<(f64, f64, f64)> = vec![(0., 0., 0.); self.vertices.len()];
+        for id in (0..self.vertices.len()).rev() {
+            let mut det = 1.;
+            let mut adj_sum = 0.;
+            let mut log_scale = 0.;
+            for _ in self.partition_leaves(id).iter() {
+                adj_sum = det;
+                det = 0.;
             }
-            for &i in &self.partition[1..] {
-                if matrix[(first_i, i)] <= min + eps {
-                    right_partition.push(i);
-                } else {
-                    left_partition.push(i);
+            for child in self.children(id) {
+                let (child_det, child_adj_sum, child_log_scale) = results[child];
+                adj_sum = adj_sum * child_det + det * child_adj_sum;
+                det *= child_det;
+                log_scale += child_log_scale;
+                let norm = f64::max(det.abs(), adj_sum.abs());
+                if norm != 0.0 {
+                    det /= norm;
+                    adj_sum /= norm;
+                    log_scale += norm.ln();
                 }
             }
+            det += weights[id] * adj_sum;
+            let norm = f64::max(det.abs(), adj_sum.abs());
+            if norm != 0.0 {
+                det /= norm;
+                adj_sum /= norm;
+                log_scale += norm.ln();
+            }
+            results[id] = (det, adj_sum, log_scale);
+        }
+        return results;
+    }
 
-            self.level = min;
-            let left_child = Box::new(UltrametricTree::new(left_partition));
-            let right_child = Box::new(UltrametricTree::new(right_partition));
-            self.children.push(left_child);
-            self.children.push(right_child);
+    /// Calculate all eigenvalues of the ultrametric matrix represented by the `UltrametricTree` in ascending order.
+    ///
+    /// The eigenvalues are calculated as in [`eigen`](UltrametricTree::eigen), without evaluating the eigenvectors.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let eigenvalues = tree.eigenvalues();
+    /// let mut expected: Vec<f64> = matrix.symmetric_eigenvalues().iter().copied().collect();
+    /// expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    ///
+    /// for (&value, &expected_value) in eigenvalues.iter().zip(expected.iter()) {
+    ///     assert!((value - expected_value).abs() < 1e-12);
+    /// }
+    /// ```
+    pub fn eigenvalues(&self) -> DVector<f64> {
+        return Spectrum::new(self).eigenvalues();
+    }
 
-            self.children[0]
-                .as_mut()
-                .from_matrix_approx_recursive(matrix, eps);
-            self.children[1]
-                .as_mut()
-                .from_matrix_approx_recursive(matrix, eps);
-        }
+    /// Calculate the eigendecomposition of the ultrametric matrix represented by the `UltrametricTree` with eigenvalues in ascending order.
+    ///
+    /// The leaves of each vertex span eigenvectors with eigenvalue zero that sum to zero on these leaves, and childless children of a vertex with equal level and number of leaves span eigenvectors with a shared eigenvalue in the same way. The remaining eigenvalues are the roots of the characteristic polynomial of the matrix reduced to vectors that are constant on the leaves of each vertex. The submatrix of a vertex minus the level of its parent is `D + weight * 1 * 1^T`, where the block diagonal matrix `D` consists of the submatrices of the children and zero for the `partition_leaves`, such that the matrix shifted by `x` is factored bottom-up in linear time by the Sherman-Morrison formula. The factorization yields the number of eigenvalues smaller than `x`, which brackets each eigenvalue and safeguards Laguerre's method on the characteristic polynomial with bisection steps, and the solutions of the shifted equation systems, which yield the eigenvectors by inverse iteration. Since each eigenvalue needs a small number of such steps, all eigenvalues are calculated in quadratic time and the eigenvectors add quadratic time, independently of the depth of the tree. Eigenvectors of clusters of close eigenvalues are orthogonalized against each other, which adds time proportional to the dimension times the squared size of each cluster.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![7.0, 1.0, 3.0, 1.0, 1.0, 5.0, 1.0, 1.0, 3.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 9.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    /// let eigen = tree.eigen();
+    ///
+    /// assert!((eigen.recompose() - matrix).norm() < 1e-12);
+    /// ```
+    pub fn eigen(&self) -> SymmetricEigen<f64, Dynamic> {
+        return Spectrum::new(self).eigen();
     }
 
-    /// Construct the ultrametric matrix that is represented by the `UltrametricTree`.
+    /// Get the elements of the matrix represented by the `UltrametricTree` at the pairs of row and column indices `pairs`.
+    ///
+    /// Each element is computed as in [`get`](UltrametricTree::get), such that `None` is returned for pairs that are out of range.
     ///
     /// # Example:
     ///
@@ -193,92 +2202,106 @@ impl UltrametricTree {
     /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
     ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
     /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
-    /// let reconstructed_matrix = tree.to_matrix();
     ///
-    /// assert_eq!(matrix, reconstructed_matrix);
+    /// assert_eq!(tree.get_many(&[(0, 2), (1, 1), (3, 4)]), vec![Some(3.0), Some(3.0), None]);
     /// ```
-    pub fn to_matrix(&self) -> DMatrix<f64> {
-        let size = self.partition.len();
-        let mut matrix = DMatrix::<f64>::zeros(size, size);
-        self.to_matrix_recursive(&mut matrix);
-        return matrix;
+    pub fn get_many(&self, pairs: &[(usize, usize)]) -> Vec<Option<f64>> {
+        return pairs.iter().map(|&(i, j)| self.get(i, j)).collect();
     }
 
-    /// Recursive function to construct the ultrametric matrix that is represented by the `UltrametricTree`.
-    fn to_matrix_recursive(&self, matrix: &mut DMatrix<f64>) {
-        for &i in self.partition_leaves.iter() {
-            matrix[(i, i)] = self.level;
-        }
-        for child_id1 in 0..self.children.len() {
-            for &i1 in self.children[child_id1].partition.iter() {
-                for child_id2 in (child_id1 + 1)..self.children.len() {
-                    for &i2 in self.children[child_id2].partition.iter() {
-                        matrix[(i1, i2)] = self.level;
-                        matrix[(i2, i1)] = self.level;
+    /// Construct the row `i` of the matrix represented by the `UltrametricTree`, which is also the column `i`, since the matrix is symmetric.
+    ///
+    /// The path from the root to the vertex, where `i` is a leaf, is traversed once. Each vertex on the path assigns its level to its `partition_leaves` and to the partitions of its children that are not on the path. Thus, each element is assigned once and the row is constructed in `O(n)` time. The function panics if `i` is out of range.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    ///
+    /// assert_eq!(ultrametric_matrix_tools::na::DVector::from_vec(vec![0.0, 1.0, 3.0, 1.0]),
+    ///     tree.row(0));
+    /// ```
+    pub fn row(&self, i: usize) -> DVector<f64> {
+        let size = self.size();
+        assert!(i < size, "index {} is out of range for size {}", i, size);
+        let mut row = DVector::<f64>::zeros(size);
+        let vertex_i = self.leaf_vertices[i];
+        let mut id = 0;
+        loop {
+            let level = self.vertices[id].level;
+            for &j in self.partition_leaves(id).iter() {
+                row[j] = level;
+            }
+            let mut next_id = None;
+            for child in self.children(id) {
+                if (child..self.vertices[child].subtree_end).contains(&vertex_i) {
+                    next_id = Some(child);
+                } else {
+                    for &j in self.partition(child).iter() {
+                        row[j] = level;
                     }
                 }
-                for &i in self.partition_leaves.iter() {
-                    matrix[(i, i1)] = self.level;
-                    matrix[(i1, i)] = self.level;
-                }
             }
-            self.children[child_id1].to_matrix_recursive(matrix);
-        }
-        for leaf_id1 in 0..self.partition_leaves.len() {
-            for leaf_id2 in (leaf_id1 + 1)..self.partition_leaves.len() {
-                let leaf1 = self.partition_leaves[leaf_id1];
-                let leaf2 = self.partition_leaves[leaf_id2];
-                matrix[(leaf1, leaf2)] = self.level;
-                matrix[(leaf2, leaf1)] = self.level;
+            match next_id {
+                Some(child) => id = child,
+                None => return row,
             }
         }
     }
 
-    /// Calculate the product of an ultrametric matrix represented by an `UltrametricTree` and a vector.
+    /// Construct the diagonal of the matrix represented by the `UltrametricTree`.
     ///
-    /// The multiplication is done in two steps. The first step is to annotate the `UltrametricTree` with the partial product. The second step sums the partial products to the full product for each element of the product vector.
+    /// The diagonal element of an index is the level of the vertex, where the index is a leaf.
     ///
     /// # Example:
     ///
     /// ```
     /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
     ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
-    /// let vector = ultrametric_matrix_tools::na::DVector::from_vec(vec![4.0, 2.0, 7.0, 5.0]);
-    /// let mut tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
-    /// let product = tree.mult(&vector);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
     ///
-    /// assert_eq!(ultrametric_matrix_tools::na::DVector::from_vec(vec![28.0, 22.0, 54.0, 18.0]),
-    ///     product);
+    /// assert_eq!(ultrametric_matrix_tools::na::DVector::from_vec(vec![0.0, 3.0, 5.0, 1.0]),
+    ///     tree.diagonal());
     /// ```
-    pub fn mult(&mut self, vector: &DVector<f64>) -> DVector<f64> {
-        self.calculate_partial_product(vector, 0.0);
-        let mut product: DVector<f64> = DVector::<f64>::zeros(vector.nrows());
-        self.calculate_full_product(&mut product, 0.0);
-        return product;
-    }
-
-    /// Recursive function to calculate the partial product, which is the first step to calculate the product in [`multiply`](UltrametricTree::multiply).
-    fn calculate_partial_product(&mut self, vector: &DVector<f64>, parent_val: f64) -> f64 {
-        let mut sum = 0.;
-        for &leaf_idx in self.partition_leaves.iter() {
-            sum += vector[leaf_idx];
-        }
-        for child in self.children.iter_mut() {
-            sum += child.calculate_partial_product(vector, self.level);
-        }
-        self.sum = (self.level - parent_val) * sum;
-        return sum;
+    pub fn diagonal(&self) -> DVector<f64> {
+        return DVector::from_iterator(
+            self.size(),
+            self.leaf_vertices.iter().map(|&id| self.vertices[id].level),
+        );
     }
 
-    /// Recursive function to calculate the full product, which is the second step to calculate the product in [`multiply`](UltrametricTree::multiply).
-    fn calculate_full_product(&self, product: &mut DVector<f64>, prev_sum: f64) {
-        let sum = prev_sum + self.sum;
-        for &leaf_id in self.partition_leaves.iter() {
-            product[leaf_id] = sum;
-        }
-        for child in self.children.iter() {
-            child.calculate_full_product(product, sum);
+    /// Calculate the sums of the rows of the matrix represented by the `UltrametricTree`.
+    ///
+    /// The sums are calculated top-down in a single pass without the multiplication with a vector of ones. The level of a vertex contributes to the indices in its `partition` once for each index of its `partition` that is not in the `partition` of the child on the path to the index.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+    ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+    /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
+    ///
+    /// assert_eq!(ultrametric_matrix_tools::na::DVector::from_vec(vec![5.0, 6.0, 10.0, 4.0]),
+    ///     tree.row_sums());
+    /// ```
+    pub fn row_sums(&self) -> DVector<f64> {
+        let mut row_sums = DVector::<f64>::zeros(self.size());
+        let mut path_sums: Vec<f64> = vec![0.; self.vertices.len()];
+        for id in 0..self.vertices.len() {
+            let vertex = &self.vertices[id];
+            let partition_size = (vertex.partition_end - vertex.partition_start) as f64;
+            for &leaf_idx in self.partition_leaves(id).iter() {
+                row_sums[leaf_idx] = path_sums[id] + vertex.level * partition_size;
+            }
+            for child in self.children(id) {
+                let child_vertex = &self.vertices[child];
+                let child_size = (child_vertex.partition_end - child_vertex.partition_start) as f64;
+                path_sums[child] = path_sums[id] + vertex.level * (partition_size - child_size);
+            }
         }
+        return row_sums;
     }
 
     /// Construct the permutation matrix of the `UltrametricTree`.
@@ -298,89 +2321,381 @@ impl UltrametricTree {
     ///     permutation_matrix);
     /// ```
     pub fn get_permutation_matrix(&self) -> DMatrix<f64> {
-        let size = self.partition.len();
-        let permutations = self.get_permutation_matrix_recursive();
+        let size = self.size();
         let mut perm_mat = DMatrix::<f64>::zeros(size, size);
-        for (i, &j) in permutations.iter().enumerate() {
+        for (i, &j) in self.permutation.iter().enumerate() {
             perm_mat[(i, j)] = 1.;
         }
         return perm_mat;
     }
+}
 
-    /// Recursive function to construct the permutation matrix.
-    fn get_permutation_matrix_recursive(&self) -> Vec<usize> {
-        let mut perm: Vec<usize> = Vec::new();
-        for child in self.children.iter() {
-            perm.extend(child.get_permutation_matrix_recursive());
+/// Convert a 2-dimensional Python array into a matrix, which is used by the Python wrappers of the constructors and checks.
+pub(crate) fn matrix_from_py(py_matrix: &PyReadonlyArrayDyn<f64>) -> PyResult<DMatrix<f64>> {
+    if py_matrix.ndim() != 2 {
+        return Err(PyValueError::new_err(format!(
+            "expected a 2-dimensional array, found {} dimensions",
+            py_matrix.ndim()
+        )));
+    }
+    let rows = py_matrix.shape()[0];
+    let columns = py_matrix.shape()[1];
+    let py_array = py_matrix.as_array();
+    let mut matrix = DMatrix::<f64>::zeros(rows, columns);
+    for i in 0..rows {
+        for j in 0..columns {
+            matrix[(i, j)] = py_array[[i, j]];
         }
-        perm.extend(self.partition_leaves.clone());
-        return perm;
     }
+    return Ok(matrix);
+}
 
-    /// Recursive function to display the `UltrametricTree`.
-    fn print_tree_recursive(&self, output_tree: &mut TreeBuilder) {
-        if self.children.is_empty() {
-            output_tree.add_empty_child(format!(
-                "{:?}, {:?}, {}",
-                self.partition, self.partition_leaves, self.level
-            ));
-        } else {
-            output_tree.begin_child(format!(
-                "{:?}, {:?}, {}",
-                self.partition, self.partition_leaves, self.level
-            ));
-            for child in self.children.iter() {
-                child.print_tree_recursive(output_tree);
-            }
-            output_tree.end_child();
-        }
+/// Convert a 1-dimensional Python array into a vector, which is used by the Python wrappers of the products and solvers.
+pub(crate) fn vector_from_py(py_vector: &PyReadonlyArrayDyn<f64>) -> PyResult<DVector<f64>> {
+    if py_vector.ndim() != 1 {
+        return Err(PyValueError::new_err(format!(
+            "expected a 1-dimensional array, found {} dimensions",
+            py_vector.ndim()
+        )));
     }
+    let size = py_vector.shape()[0];
+    let py_array = py_vector.as_array();
+    let mut vector = DVector::<f64>::zeros(size);
+    for i in 0..size {
+        vector[i] = py_array[[i]];
+    }
+    return Ok(vector);
 }
 
 #[pymethods]
 impl UltrametricTree {
-    /// Python wrapper for [`from_matrix`](UltrametricTree::from_matrix).
+    /// Python wrapper for [`from_matrix`](UltrametricTree::from_matrix), which checks the matrix as [`try_from_matrix`](UltrametricTree::try_from_matrix).
+    ///
+    /// A `ValueError` is raised if the matrix is not ultrametric, since [`from_matrix`](UltrametricTree::from_matrix) may panic for such matrices, e.g. for NaN elements.
     #[new]
-    pub fn from_matrix_py(py_matrix: PyReadonlyArrayDyn<f64>) -> Self {
-        let size = py_matrix.shape()[0];
-        let py_array = py_matrix.as_array();
-        let mut matrix = DMatrix::<f64>::zeros(size, size);
-        for i in 0..size {
-            for j in 0..size {
-                matrix[(i, j)] = py_array[[i, j]];
+    pub fn from_matrix_py(py_matrix: PyReadonlyArrayDyn<f64>) -> PyResult<Self> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        return Ok(UltrametricTree::try_from_matrix(&matrix)?);
+    }
+
+    /// Python wrapper for [`try_from_matrix`](UltrametricTree::try_from_matrix).
+    ///
+    /// A `ValueError` is raised if the matrix is not ultrametric.
+    #[staticmethod]
+    #[pyo3(name = "try_from_matrix")]
+    pub fn try_from_matrix_py(py_matrix: PyReadonlyArrayDyn<f64>) -> PyResult<Self> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        return Ok(UltrametricTree::try_from_matrix(&matrix)?);
+    }
+
+    /// Python wrapper for [`subdominant`](UltrametricTree::subdominant).
+    #[staticmethod]
+    #[pyo3(name = "subdominant")]
+    pub fn subdominant_py(py_matrix: PyReadonlyArrayDyn<f64>) -> PyResult<(Self, f64)> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        return Ok(UltrametricTree::subdominant(&matrix)?);
+    }
+
+    /// Python wrapper for [`linf_fit`](UltrametricTree::linf_fit).
+    #[staticmethod]
+    #[pyo3(name = "linf_fit")]
+    pub fn linf_fit_py(py_matrix: PyReadonlyArrayDyn<f64>) -> PyResult<(Self, f64)> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        return Ok(UltrametricTree::linf_fit(&matrix)?);
+    }
+
+    /// Python wrapper for [`least_squares_fit`](UltrametricTree::least_squares_fit).
+    ///
+    /// A `ValueError` is raised if the matrix or the weights are not square 2-dimensional arrays of the same size.
+    #[staticmethod]
+    #[pyo3(name = "least_squares_fit")]
+    #[args(py_weights = "None")]
+    pub fn least_squares_fit_py(
+        py_matrix: PyReadonlyArrayDyn<f64>,
+        py_weights: Option<PyReadonlyArrayDyn<f64>>,
+    ) -> PyResult<(Self, f64)> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        let weights = match py_weights {
+            Some(py_weights) => {
+                let weights = matrix_from_py(&py_weights)?;
+                if !weights.is_square() {
+                    return Err(UltrametricError::NonSquareMatrix {
+                        rows: weights.nrows(),
+                        columns: weights.ncols(),
+                    }
+                    .into());
+                }
+                Some(weights)
+            }
+            None => None,
+        };
+        return Ok(UltrametricTree::least_squares_fit(
+            &matrix,
+            weights.as_ref(),
+        )?);
+    }
+
+    /// Python wrapper for [`from_linkage`](UltrametricTree::from_linkage).
+    #[staticmethod]
+    #[pyo3(name = "from_linkage")]
+    pub fn from_linkage_py(py_linkage: PyReadonlyArrayDyn<f64>, c: f64) -> PyResult<Self> {
+        if py_linkage.ndim() != 2 {
+            return Err(PyValueError::new_err(format!(
+                "expected a 2-dimensional array, found {} dimensions",
+                py_linkage.ndim()
+            )));
+        }
+        let rows = py_linkage.shape()[0];
+        let columns = py_linkage.shape()[1];
+        let py_array = py_linkage.as_array();
+        let mut linkage = DMatrix::<f64>::zeros(rows, columns);
+        for i in 0..rows {
+            for j in 0..columns {
+                linkage[(i, j)] = py_array[[i, j]];
+            }
+        }
+
+        return Ok(UltrametricTree::from_linkage(&linkage, c)?);
+    }
+
+    /// Python wrapper for [`to_linkage`](UltrametricTree::to_linkage).
+    #[pyo3(name = "to_linkage")]
+    pub fn to_linkage_py<'py>(&self, py: Python<'py>, c: f64) -> &'py PyArray2<f64> {
+        let linkage = self.to_linkage(c);
+        let mut py_linkage = Array2::zeros((linkage.nrows(), 4));
+        for i in 0..linkage.nrows() {
+            for j in 0..4 {
+                py_linkage[[i, j]] = linkage[(i, j)];
             }
         }
+        return py_linkage.into_pyarray(py);
+    }
+
+    /// Python wrapper for [`from_newick`](UltrametricTree::from_newick), which returns the tree and the list of the labels of the leaves.
+    #[staticmethod]
+    #[pyo3(name = "from_newick")]
+    pub fn from_newick_py(newick: &str) -> PyResult<(Self, Vec<String>)> {
+        return Ok(UltrametricTree::from_newick(newick)?);
+    }
+
+    /// Python wrapper for [`to_newick`](UltrametricTree::to_newick), where the labels are an optional keyword argument.
+    #[pyo3(name = "to_newick")]
+    #[args(labels = "None")]
+    pub fn to_newick_py(&self, labels: Option<Vec<String>>) -> PyResult<String> {
+        return Ok(self.to_newick(labels.as_deref())?);
+    }
+
+    /// Python wrapper for [`write_binary`](UltrametricTree::write_binary), which writes the tree to the file at `path`.
+    #[pyo3(name = "write_binary")]
+    pub fn write_binary_py(&self, path: &str) -> PyResult<()> {
+        let mut writer = io::BufWriter::new(File::create(path)?);
+        self.write_binary(&mut writer)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    /// Python wrapper for [`to_dot`](UltrametricTree::to_dot), where the fields of the [`DotOptions`] are keyword arguments.
+    #[pyo3(name = "to_dot")]
+    #[args(max_partition_size = "None", color_by_level = "false")]
+    pub fn to_dot_py(&self, max_partition_size: Option<usize>, color_by_level: bool) -> String {
+        return self.to_dot(&DotOptions {
+            max_partition_size,
+            color_by_level,
+        });
+    }
+
+    /// Python wrapper for [`to_svg`](UltrametricTree::to_svg), where the fields of the [`SvgOptions`] are keyword arguments and the orientation is one of `"top"`, `"bottom"`, `"left"` and `"right"`.
+    #[pyo3(name = "to_svg")]
+    #[args(
+        width = "800.0",
+        height = "400.0",
+        orientation = "\"top\"",
+        labels = "None",
+        show_labels = "true",
+        font_size = "12.0"
+    )]
+    pub fn to_svg_py(
+        &self,
+        width: f64,
+        height: f64,
+        orientation: &str,
+        labels: Option<Vec<String>>,
+        show_labels: bool,
+        font_size: f64,
+    ) -> PyResult<String> {
+        let orientation = match orientation {
+            "top" => DendrogramOrientation::Top,
+            "bottom" => DendrogramOrientation::Bottom,
+            "left" => DendrogramOrientation::Left,
+            "right" => DendrogramOrientation::Right,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown orientation {:?}, expected \"top\", \"bottom\", \"left\" or \"right\"",
+                    orientation
+                )))
+            }
+        };
+        return Ok(self.to_svg(&SvgOptions {
+            width,
+            height,
+            orientation,
+            labels,
+            show_labels,
+            font_size,
+        }));
+    }
 
-        let vertex_ids: Vec<usize> = (0..matrix.nrows()).collect();
-        let mut root = UltrametricTree::new(vertex_ids);
-        root.from_matrix_recursive(&matrix);
-        return root;
+    /// Python wrapper for [`write_tree`](UltrametricTree::write_tree), which returns the text, where the fields of the [`PrintOptions`] are keyword arguments.
+    #[pyo3(name = "format_tree")]
+    #[args(
+        show_partition = "true",
+        max_indices = "None",
+        precision = "None",
+        show_subtree_size = "false"
+    )]
+    pub fn format_tree_py(
+        &self,
+        show_partition: bool,
+        max_indices: Option<usize>,
+        precision: Option<usize>,
+        show_subtree_size: bool,
+    ) -> String {
+        let options = PrintOptions {
+            show_partition,
+            max_indices,
+            precision,
+            show_subtree_size,
+        };
+        let mut text: Vec<u8> = Vec::new();
+        self.write_tree(&mut text, &options).ok();
+        return String::from_utf8_lossy(&text).into_owned();
     }
 
-    /// Python wrapper for [`mult`](UltrametricTree::mult).
+    /// Python wrapper for [`mult`](UltrametricTree::mult) and [`mult_matrix`](UltrametricTree::mult_matrix), which multiplies a 1-dimensional vector or all columns of a 2-dimensional array. If the number of rows differs from the size of the matrix, then [`UltrametricError::DimensionMismatch`] is returned.
     #[pyo3(name = "mult")]
     pub fn mult_py<'py>(
-        &mut self,
+        &self,
         py: Python<'py>,
         py_vector: PyReadonlyArrayDyn<f64>,
-    ) -> &'py PyArray1<f64> {
-        let size = py_vector.shape()[0];
+    ) -> PyResult<&'py PyAny> {
         let py_array = py_vector.as_array();
-        let mut vector = DVector::<f64>::zeros(size);
-        for i in 0..size {
-            vector[i] = py_array[[i]];
+        match py_vector.ndim() {
+            1 => {
+                let size = py_vector.shape()[0];
+                if size != self.size() {
+                    return Err(UltrametricError::DimensionMismatch {
+                        expected: self.size(),
+                        found: size,
+                    }
+                    .into());
+                }
+                let mut vector = DVector::<f64>::zeros(size);
+                for i in 0..size {
+                    vector[i] = py_array[[i]];
+                }
+
+                let product = self.mult(&vector);
+                let py_product = PyArray1::from_vec(py, product.data.as_vec().clone());
+                return Ok(py_product.as_ref());
+            }
+            2 => {
+                let rows = py_vector.shape()[0];
+                let columns = py_vector.shape()[1];
+                if rows != self.size() {
+                    return Err(UltrametricError::DimensionMismatch {
+                        expected: self.size(),
+                        found: rows,
+                    }
+                    .into());
+                }
+                let mut matrix = DMatrix::<f64>::zeros(rows, columns);
+                for i in 0..rows {
+                    for j in 0..columns {
+                        matrix[(i, j)] = py_array[[i, j]];
+                    }
+                }
+
+                let product = self.mult_matrix(&matrix);
+                let mut py_product = Array2::zeros((rows, columns));
+                for i in 0..rows {
+                    for j in 0..columns {
+                        py_product[[i, j]] = product[(i, j)];
+                    }
+                }
+                return Ok(py_product.into_pyarray(py).as_ref());
+            }
+            ndim => {
+                return Err(PyValueError::new_err(format!(
+                    "expected a 1-dimensional or 2-dimensional array, found {} dimensions",
+                    ndim
+                )))
+            }
         }
+    }
 
-        self.calculate_partial_product(&vector, 0.0);
-        let mut product: DVector<f64> = DVector::<f64>::zeros(vector.nrows());
-        self.calculate_full_product(&mut product, 0.0);
+    /// Python wrapper for [`solve`](UltrametricTree::solve).
+    ///
+    /// A `ValueError` is raised if the vector is not a 1-dimensional array of the size of the matrix or if the matrix is singular.
+    #[pyo3(name = "solve")]
+    pub fn solve_py<'py>(
+        &self,
+        py: Python<'py>,
+        py_vector: PyReadonlyArrayDyn<f64>,
+    ) -> PyResult<&'py PyArray1<f64>> {
+        let vector = vector_from_py(&py_vector)?;
+        let solution = self.solve(&vector)?;
+        let py_solution = PyArray1::from_vec(py, solution.data.as_vec().clone());
+        return Ok(py_solution);
+    }
+
+    /// Python wrapper for [`inverse`](UltrametricTree::inverse).
+    #[pyo3(name = "inverse")]
+    pub fn inverse_py(&self) -> PyResult<UltrametricInverse> {
+        return Ok(self.inverse()?);
+    }
+
+    /// Python wrapper for [`det`](UltrametricTree::det).
+    #[pyo3(name = "det")]
+    pub fn det_py(&self) -> f64 {
+        return self.det();
+    }
+
+    /// Python wrapper for [`log_det`](UltrametricTree::log_det).
+    #[pyo3(name = "log_det")]
+    pub fn log_det_py(&self) -> (f64, f64) {
+        return self.log_det();
+    }
+
+    /// Python wrapper for [`eigenvalues`](UltrametricTree::eigenvalues).
+    #[pyo3(name = "eigenvalues")]
+    pub fn eigenvalues_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        let eigenvalues = self.eigenvalues();
+        return PyArray1::from_vec(py, eigenvalues.data.as_vec().clone());
+    }
 
-        let py_product = PyArray1::from_vec(py, product.data.as_vec().clone());
-        return py_product;
+    /// Python wrapper for [`eigen`](UltrametricTree::eigen).
+    ///
+    /// Similar to `numpy.linalg.eigh`, the eigenvalues and the matrix whose columns are the eigenvectors are returned.
+    #[pyo3(name = "eigen")]
+    pub fn eigen_py<'py>(&self, py: Python<'py>) -> (&'py PyArray1<f64>, &'py PyArray2<f64>) {
+        let size = self.size();
+        let eigen = self.eigen();
+        let mut py_eigenvectors = Array2::zeros((size, size));
+        for i in 0..size {
+            for j in 0..size {
+                py_eigenvectors[[i, j]] = eigen.eigenvectors[(i, j)];
+            }
+        }
+        return (
+            PyArray1::from_vec(py, eigen.eigenvalues.data.as_vec().clone()),
+            py_eigenvectors.into_pyarray(py),
+        );
     }
 
     /// Get the element of the matrix represented by the `UltrametricTree` at row index `i` and column index `j`.
     ///
+    /// The element is the level of the lowest common ancestor of the vertices, where `i` and `j` are leaves. The lowest common ancestor is found with an index that is built with the tree, such that the element is returned in `O(log(n))` time. If `i` or `j` is out of range, `None` is returned.
+    ///
     /// # Example:
     ///
     /// ```
@@ -388,36 +2703,61 @@ impl UltrametricTree {
     ///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
     /// let tree = ultrametric_matrix_tools::UltrametricTree::from_matrix(&matrix);
     ///
-    /// assert_eq!(*tree.get(0, 2), Some(3.0));
+    /// assert_eq!(tree.get(0, 2), Some(3.0));
     /// ```
     pub fn get(&self, i: usize, j: usize) -> Option<f64> {
-        let size = self.partition.len();
+        let size = self.size();
         if i >= size || j >= size {
             return None;
         }
-        return Some(self.get_recursive(i, j));
+        let id = self
+            .lca_index
+            .lca(self.leaf_vertices[i], self.leaf_vertices[j]);
+        return Some(self.vertices[id].level);
     }
 
-    /// Recursive function to get the element of the matrix represented by the `UltrametricTree` at row index `i` and column index `j`.
-    fn get_recursive(&self, i: usize, j: usize) -> f64 {
-        if self.partition_leaves.contains(&i) || self.partition_leaves.contains(&j) {
-            return self.level;
-        }
-        for child in self.children.iter() {
-            if child.partition.contains(&i) && child.partition.contains(&j) {
-                return child.get_recursive(i, j);
-            }
+    /// Python wrapper for [`get_many`](UltrametricTree::get_many).
+    #[pyo3(name = "get_many")]
+    pub fn get_many_py(&self, pairs: Vec<(usize, usize)>) -> Vec<Option<f64>> {
+        return self.get_many(&pairs);
+    }
+
+    /// Python wrapper for [`row`](UltrametricTree::row).
+    ///
+    /// If `i` is out of range, an `IndexError` is raised.
+    #[pyo3(name = "row")]
+    pub fn row_py<'py>(&self, py: Python<'py>, i: usize) -> PyResult<&'py PyArray1<f64>> {
+        if i >= self.size() {
+            return Err(PyIndexError::new_err(format!(
+                "index {} is out of range for size {}",
+                i,
+                self.size()
+            )));
         }
-        return self.level;
+        let row = self.row(i);
+        return Ok(PyArray1::from_vec(py, row.data.as_vec().clone()));
+    }
+
+    /// Python wrapper for [`diagonal`](UltrametricTree::diagonal).
+    #[pyo3(name = "diagonal")]
+    pub fn diagonal_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        let diagonal = self.diagonal();
+        return PyArray1::from_vec(py, diagonal.data.as_vec().clone());
+    }
+
+    /// Python wrapper for [`row_sums`](UltrametricTree::row_sums).
+    #[pyo3(name = "row_sums")]
+    pub fn row_sums_py<'py>(&self, py: Python<'py>) -> &'py PyArray1<f64> {
+        let row_sums = self.row_sums();
+        return PyArray1::from_vec(py, row_sums.data.as_vec().clone());
     }
 
     /// Python wrapper for [`get_permutation_matrix`](UltrametricTree::get_permutation_matrix).
     #[pyo3(name = "get_permutation_matrix")]
     pub fn get_permutation_matrix_py<'py>(&self, py: Python<'py>) -> &'py PyArray2<f64> {
-        let size = self.partition.len();
-        let permutations = self.get_permutation_matrix_recursive();
+        let size = self.size();
         let mut perm_mat = DMatrix::<f64>::zeros(size, size);
-        for (i, &j) in permutations.iter().enumerate() {
+        for (i, &j) in self.permutation.iter().enumerate() {
             perm_mat[(i, j)] = 1.;
         }
 
@@ -452,27 +2792,34 @@ impl UltrametricTree {
     /// └─ [1], [1], 3
     /// ```
     pub fn prune_tree(&mut self) {
-        let mut new_children: Vec<Box<UltrametricTree>> = Vec::new();
-        let mut remove_ids: Vec<usize> = Vec::new();
-        for (id, child) in self.children.iter_mut().enumerate() {
-            child.prune_tree();
-            if child.level == self.level {
-                self.partition_leaves.extend(&child.partition_leaves);
-                new_children.append(&mut child.children);
-                remove_ids.push(id);
+        let mut builders: Vec<VertexBuilder> = Vec::new();
+        builders.resize_with(self.vertices.len(), Default::default);
+        for id in (0..self.vertices.len()).rev() {
+            let level = self.vertices[id].level;
+            let mut leaves = self.partition_leaves(id).to_vec();
+            let mut children: Vec<usize> = Vec::new();
+            let mut new_children: Vec<usize> = Vec::new();
+            for child in self.children(id) {
+                if builders[child].level == level {
+                    leaves.append(&mut builders[child].leaves);
+                    new_children.append(&mut builders[child].children);
+                } else {
+                    children.push(child);
+                }
             }
+            children.extend(new_children);
+            builders[id] = VertexBuilder {
+                level,
+                children,
+                leaves,
+            };
         }
-        remove_ids.sort();
-        remove_ids.reverse();
-        for &id in remove_ids.iter() {
-            self.children.remove(id);
-        }
-        self.children.extend(new_children);
+        *self = UltrametricTree::from_builders(&builders, 0, self.size());
     }
 
     /// Displays the `UltrametricTree`.
     ///
-    /// The structure of the `UltrametricTree` is printed to the terminal. This includes the current vertex and all the children of this vertex. Each vertex of the printed tree is annotated with `partition`, `partition_leaves` and `level`.
+    /// The structure of the `UltrametricTree` is printed to the terminal. This includes the current vertex and all the children of this vertex. Each vertex of the printed tree is annotated with `partition`, `partition_leaves` and `level`. Errors of the terminal are ignored, see [`write_tree`](UltrametricTree::write_tree) to write the tree to any writer with custom [`PrintOptions`].
     ///
     /// # Example:
     ///
@@ -493,14 +2840,293 @@ impl UltrametricTree {
     ///    └─ [3], [3], 1
     /// ```
     pub fn print_tree(&self) {
-        let mut tree_root = TreeBuilder::new(format!(
-            "{:?}, {:?}, {}",
-            self.partition, self.partition_leaves, self.level
+        self.write_tree(io::stdout().lock(), &PrintOptions::default())
+            .ok();
+    }
+}
+
+#[pyproto]
+impl PyObjectProtocol for UltrametricTree {
+    fn __str__(&self) -> String {
+        return format!("{}", self);
+    }
+
+    fn __repr__(&self) -> String {
+        return format!("{:?}", self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Caterpillar tree of size `size`, where the vertex `i` has the level `i + 1`, the leaf `i` and the vertex `i + 1` as its child.
+    fn caterpillar_tree(size: usize) -> UltrametricTree {
+        let builders: Vec<VertexBuilder> = (0..size)
+            .map(|i| VertexBuilder {
+                level: (i + 1) as f64,
+                children: if i + 1 < size {
+                    vec![i + 1]
+                } else {
+                    Vec::new()
+                },
+                leaves: vec![i],
+            })
+            .collect();
+        return UltrametricTree::from_builders(&builders, 0, size);
+    }
+
+    #[test]
+    fn try_from_matrix_errors() {
+        let matrix = DMatrix::from_vec(3, 3, vec![4.0, 1.0, 3.0, 1.0, 4.0, 1.0, 3.0, 1.0, 4.0]);
+        assert!(UltrametricTree::try_from_matrix(&matrix).is_ok());
+        assert_eq!(
+            UltrametricTree::try_from_matrix(&DMatrix::zeros(0, 0)).err(),
+            Some(UltrametricError::EmptyMatrix)
+        );
+        assert_eq!(
+            UltrametricTree::try_from_matrix(&DMatrix::zeros(2, 3)).err(),
+            Some(UltrametricError::NonSquareMatrix {
+                rows: 2,
+                columns: 3
+            })
+        );
+        let mut not_a_number = matrix.clone();
+        not_a_number[(1, 2)] = f64::NAN;
+        assert_eq!(
+            UltrametricTree::try_from_matrix(&not_a_number).err(),
+            Some(UltrametricError::NotANumber { row: 1, column: 2 })
+        );
+        let mut non_symmetric = matrix.clone();
+        non_symmetric[(0, 2)] = 2.0;
+        assert_eq!(
+            UltrametricTree::try_from_matrix(&non_symmetric).err(),
+            Some(UltrametricError::NonSymmetric { row: 0, column: 2 })
+        );
+        let mut negative = matrix.clone();
+        negative[(0, 1)] = -1.0;
+        negative[(1, 0)] = -1.0;
+        assert_eq!(
+            UltrametricTree::try_from_matrix(&negative).err(),
+            Some(UltrametricError::NegativeElement { row: 0, column: 1 })
+        );
+        let mut non_ultrametric = matrix.clone();
+        non_ultrametric[(1, 2)] = 2.0;
+        non_ultrametric[(2, 1)] = 2.0;
+        assert!(matches!(
+            UltrametricTree::try_from_matrix(&non_ultrametric).err(),
+            Some(UltrametricError::NonUltrametric { .. })
         ));
-        for child in self.children.iter() {
-            child.print_tree_recursive(&mut tree_root);
+    }
+
+    #[test]
+    fn from_linkage_errors() {
+        let linkage = DMatrix::from_row_slice(2, 4, &[0.0, 2.0, 1.0, 2.0, 1.0, 3.0, 3.0, 3.0]);
+        assert!(UltrametricTree::from_linkage(&linkage, 4.0).is_ok());
+        assert_eq!(
+            UltrametricTree::from_linkage(&DMatrix::zeros(2, 3), 4.0).err(),
+            Some(UltrametricError::DimensionMismatch {
+                expected: 4,
+                found: 3
+            })
+        );
+        let invalid_linkages: [([f64; 8], usize); 6] = [
+            ([0.0, 5.0, 1.0, 2.0, 1.0, 3.0, 3.0, 3.0], 0),
+            ([0.0, 2.5, 1.0, 2.0, 1.0, 3.0, 3.0, 3.0], 0),
+            ([0.0, 0.0, 1.0, 2.0, 1.0, 3.0, 3.0, 3.0], 0),
+            ([0.0, 2.0, 1.0, 3.0, 1.0, 3.0, 3.0, 3.0], 0),
+            ([0.0, 2.0, 1.0, 2.0, 0.0, 3.0, 3.0, 3.0], 1),
+            ([0.0, 2.0, 1.0, 2.0, 1.0, 3.0, 0.5, 3.0], 1),
+        ];
+        for &(rows, row) in invalid_linkages.iter() {
+            let linkage = DMatrix::from_row_slice(2, 4, &rows);
+            assert_eq!(
+                UltrametricTree::from_linkage(&linkage, 4.0).err(),
+                Some(UltrametricError::InvalidLinkage { row })
+            );
+        }
+    }
+
+    #[test]
+    fn subdominant_errors() {
+        assert_eq!(
+            UltrametricTree::subdominant(&DMatrix::zeros(3, 2)).err(),
+            Some(UltrametricError::NonSquareMatrix {
+                rows: 3,
+                columns: 2
+            })
+        );
+    }
+
+    #[test]
+    fn linf_fit_errors() {
+        assert_eq!(
+            UltrametricTree::linf_fit(&DMatrix::zeros(2, 3)).err(),
+            Some(UltrametricError::NonSquareMatrix {
+                rows: 2,
+                columns: 3
+            })
+        );
+    }
+
+    #[test]
+    fn least_squares_fit_errors() {
+        assert_eq!(
+            UltrametricTree::least_squares_fit(&DMatrix::zeros(2, 3), None).err(),
+            Some(UltrametricError::NonSquareMatrix {
+                rows: 2,
+                columns: 3
+            })
+        );
+        assert_eq!(
+            UltrametricTree::least_squares_fit(&DMatrix::zeros(3, 3), Some(&DMatrix::zeros(3, 2)))
+                .err(),
+            Some(UltrametricError::DimensionMismatch {
+                expected: 3,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn newick_round_trip() {
+        let matrix = DMatrix::from_vec(
+            4,
+            4,
+            vec![
+                0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 1.0, 1.0, 1.0, 1.0,
+            ],
+        );
+        let tree = UltrametricTree::from_matrix(&matrix);
+        let (parsed_tree, labels) =
+            UltrametricTree::from_newick(&tree.to_newick(None).unwrap()).unwrap();
+        let indices: Vec<usize> = labels.iter().map(|label| label.parse().unwrap()).collect();
+        let parsed_matrix = parsed_tree.to_matrix();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_eq!(parsed_matrix[(i, j)], matrix[(indices[i], indices[j])]);
+            }
+        }
+    }
+
+    #[test]
+    fn to_newick_dimension_mismatch() {
+        assert_eq!(
+            caterpillar_tree(3)
+                .to_newick(Some(&["A".to_string()]))
+                .err(),
+            Some(UltrametricError::DimensionMismatch {
+                expected: 3,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn solve_dimension_mismatch() {
+        assert_eq!(
+            caterpillar_tree(4).solve(&DVector::zeros(3)).err(),
+            Some(UltrametricError::DimensionMismatch {
+                expected: 4,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "dimension mismatch")]
+    fn mult_matrix_dimension_mismatch() {
+        caterpillar_tree(4).mult_matrix(&DMatrix::zeros(3, 2));
+    }
+
+    #[test]
+    fn deep_caterpillar_is_stack_safe() {
+        let size = 100_000;
+        let tree = caterpillar_tree(size);
+        let ones = DVector::from_element(size, 1.0);
+        let product = tree.mult(&ones);
+        for i in 0..size {
+            let (i_f, size_f) = (i as f64, size as f64);
+            let expected = (i_f + 1.0) * (i_f + 2.0) / 2.0 + (size_f - 1.0 - i_f) * (i_f + 1.0);
+            assert_eq!(product[i], expected);
+        }
+        let solution = tree.solve(&product).unwrap();
+        assert!((solution - &ones).amax() < 1e-6);
+        assert_eq!(tree.row(size - 1)[0], 1.0);
+        let newick = tree.to_newick(None).unwrap();
+        let (parsed_tree, labels) = UltrametricTree::from_newick(&newick).unwrap();
+        let parsed_product = parsed_tree.mult(&ones);
+        for (k, label) in labels.iter().enumerate() {
+            assert_eq!(parsed_product[k], product[label.parse::<usize>().unwrap()]);
+        }
+    }
+
+    #[test]
+    fn caterpillar_eigen() {
+        let size = 200;
+        let tree = caterpillar_tree(size);
+        let matrix = tree.to_matrix();
+        let mut expected: Vec<f64> = matrix.symmetric_eigenvalues().iter().copied().collect();
+        expected.sort_by(|a, b| a.total_cmp(b));
+        let eigen = tree.eigen();
+        for (&value, &expected_value) in eigen.eigenvalues.iter().zip(expected.iter()) {
+            assert!((value - expected_value).abs() < 1e-9 * expected[size - 1]);
+        }
+        for (&value, &eigen_value) in tree.eigenvalues().iter().zip(eigen.eigenvalues.iter()) {
+            assert_eq!(value, eigen_value);
+        }
+        let orthogonality = eigen.eigenvectors.transpose() * &eigen.eigenvectors;
+        assert!((orthogonality - DMatrix::identity(size, size)).amax() < 1e-10);
+        assert!((eigen.recompose() - &matrix).amax() < 1e-9 * expected[size - 1]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn rayon_is_identical_to_serial() {
+        use crate::utils::random_ultrametric_matrix;
+
+        let size = 2 * PARALLEL_MIN_SIZE;
+        let matrix = random_ultrametric_matrix(size);
+        let tree = UltrametricTree::from_matrix(&matrix);
+
+        let mut serial_tree = UltrametricTree::new(size);
+        serial_tree.vertices = vec![Vertex::default(); 2 * size - 1];
+        UltrametricTree::split_subtree(
+            &matrix,
+            &|partition: &[usize]| split_partition(&matrix, partition),
+            &mut serial_tree.vertices,
+            &mut serial_tree.permutation,
+            (0, 0, size),
+            0,
+        );
+        assert_eq!(tree.permutation, serial_tree.permutation);
+        for (vertex, serial_vertex) in tree.vertices.iter().zip(serial_tree.vertices.iter()) {
+            assert_eq!(vertex.level.to_bits(), serial_vertex.level.to_bits());
+            assert_eq!(
+                [
+                    vertex.subtree_end,
+                    vertex.partition_start,
+                    vertex.leaf_start,
+                    vertex.partition_end
+                ],
+                [
+                    serial_vertex.subtree_end,
+                    serial_vertex.partition_start,
+                    serial_vertex.leaf_start,
+                    serial_vertex.partition_end
+                ]
+            );
+        }
+
+        let vector = DVector::from_fn(size, |i, _| ((i * 7919) % 1013) as f64 - 500.0);
+        let mut sums = vec![0.; tree.vertices.len()];
+        tree.partial_product_subtree(&vector, 0, &mut sums);
+        sums[0] *= tree.vertices[0].level;
+        tree.full_product_subtree(0, &mut sums);
+        let serial_product = DVector::from_fn(size, |i, _| sums[tree.leaf_vertices[i]]);
+        let product = tree.mult(&vector);
+        for i in 0..size {
+            assert_eq!(product[i].to_bits(), serial_product[i].to_bits());
         }
-        let tree = tree_root.build();
-        print_tree(&tree).ok();
     }
 }
@@ -1,25 +1,91 @@
 //! Usful funtions to construct and check propertyies of ultrametric matrices.
 
+use crate::ultrametric_tree::matrix_from_py;
 use nalgebra::DMatrix;
 use ndarray::prelude::*;
 use numpy::{IntoPyArray, PyArray2, PyReadonlyArrayDyn};
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use rand::prelude::*;
 
 #[pymodule]
 fn utils(_py: Python, m: &PyModule) -> PyResult<()> {
+    /// Python wrapper for [`is_ultrametric`](is_ultrametric).
+    ///
+    /// A `ValueError` is raised if the matrix is not a 2-dimensional array, while `False` is returned for non-square matrices.
     #[pyfn(m)]
     #[pyo3(name = "is_ultrametric")]
-    pub fn is_ultrametric_py(py_matrix: PyReadonlyArrayDyn<f64>) -> bool {
-        let size = py_matrix.shape()[0];
-        let py_array = py_matrix.as_array();
-        let mut matrix = DMatrix::<f64>::zeros(size, size);
-        for i in 0..size {
-            for j in 0..size {
-                matrix[(i, j)] = py_array[[i, j]];
+    pub fn is_ultrametric_py(py_matrix: PyReadonlyArrayDyn<f64>) -> PyResult<bool> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        return Ok(self::is_ultrametric(&matrix));
+    }
+
+    /// Python wrapper for [`is_ultrametric_with_tolerance`](is_ultrametric_with_tolerance).
+    ///
+    /// A `ValueError` is raised if the matrix is not a 2-dimensional array, while `False` is returned for non-square matrices.
+    #[pyfn(m)]
+    #[pyo3(name = "is_ultrametric_with_tolerance")]
+    pub fn is_ultrametric_with_tolerance_py(
+        py_matrix: PyReadonlyArrayDyn<f64>,
+        abs_tol: f64,
+        rel_tol: f64,
+    ) -> PyResult<bool> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        return Ok(self::is_ultrametric_with_tolerance(
+            &matrix, abs_tol, rel_tol,
+        ));
+    }
+
+    /// Python wrapper for [`check_ultrametric`](check_ultrametric).
+    ///
+    /// The report is returned as dict with the keys `is_ultrametric`, `max_violation` and `violation`. The violation is `None` or a dict with the key `kind`, the indices of the violation as `indices` and the `level` for violations of blocks.
+    #[pyfn(m)]
+    #[pyo3(name = "check_ultrametric")]
+    pub fn check_ultrametric_py<'py>(
+        py: Python<'py>,
+        py_matrix: PyReadonlyArrayDyn<f64>,
+    ) -> PyResult<&'py PyDict> {
+        let matrix = matrix_from_py(&py_matrix)?;
+        let report = self::check_ultrametric(&matrix);
+        let py_report = PyDict::new(py);
+        py_report.set_item("is_ultrametric", report.is_ultrametric())?;
+        py_report.set_item("max_violation", report.max_violation)?;
+        let py_violation = match report.violation {
+            None => None,
+            Some(violation) => {
+                let py_violation = PyDict::new(py);
+                match violation {
+                    UltrametricViolation::NonSquare { rows, columns } => {
+                        py_violation.set_item("kind", "non_square")?;
+                        py_violation.set_item("indices", (rows, columns))?;
+                    }
+                    UltrametricViolation::NotANumber { row, column } => {
+                        py_violation.set_item("kind", "nan")?;
+                        py_violation.set_item("indices", (row, column))?;
+                    }
+                    UltrametricViolation::Asymmetric { row, column } => {
+                        py_violation.set_item("kind", "asymmetric")?;
+                        py_violation.set_item("indices", (row, column))?;
+                    }
+                    UltrametricViolation::Negative { row, column } => {
+                        py_violation.set_item("kind", "negative")?;
+                        py_violation.set_item("indices", (row, column))?;
+                    }
+                    UltrametricViolation::Triple { i, j, k } => {
+                        py_violation.set_item("kind", "triple")?;
+                        py_violation.set_item("indices", (i, j, k))?;
+                    }
+                    UltrametricViolation::Block { row, column, level } => {
+                        py_violation.set_item("kind", "block")?;
+                        py_violation.set_item("indices", (row, column))?;
+                        py_violation.set_item("level", level)?;
+                    }
+                }
+                Some(py_violation)
             }
-        }
-        return self::is_ultrametric(&matrix);
+        };
+        py_report.set_item("violation", py_violation)?;
+        return Ok(py_report);
     }
     #[pyfn(m)]
     #[pyo3(name = "random_ultrametric_matrix")]
@@ -54,7 +120,7 @@ fn utils(_py: Python, m: &PyModule) -> PyResult<()> {
 
 /// Checks if a matrix is ultrametric.
 ///
-/// To check the ultrametric property for a matrix, a similar algorithm as `from_matrix` for `UltrametricTree` is used. First, the symmetry is checked. If the matrix is symmetric, then it is recursively checked if a permutation of the matrix can be partitioned such that the off-diagonal blocks have one value and the diagonal blocks are ultrametric.
+/// To check the ultrametric property for a matrix, a similar algorithm as `from_matrix` for `UltrametricTree` is used. First, the symmetry is checked. If the matrix is symmetric, then it is checked if a permutation of the matrix can be partitioned such that the off-diagonal blocks have one value and the diagonal blocks are ultrametric. The check stops at the first violation, whose reason is reported by [`check_ultrametric`].
 ///
 /// # Example:
 /// ```
@@ -67,71 +133,241 @@ fn utils(_py: Python, m: &PyModule) -> PyResult<()> {
 /// assert_eq!(ultrametric_matrix_tools::utils::is_ultrametric(&not_ultrametric), false);
 /// ```
 pub fn is_ultrametric(matrix: &DMatrix<f64>) -> bool {
-    if !is_symmetric(matrix) {
-        return false;
+    return find_violations(matrix, true).is_ultrametric();
+}
+
+/// Violation of the ultrametric property found by [`check_ultrametric`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum UltrametricViolation {
+    /// The matrix with `rows` rows and `columns` columns is not square.
+    NonSquare {
+        /// Number of rows of the matrix
+        rows: usize,
+        /// Number of columns of the matrix
+        columns: usize,
+    },
+    /// The element at `(row, column)` is NaN.
+    NotANumber {
+        /// Row index of the element
+        row: usize,
+        /// Column index of the element
+        column: usize,
+    },
+    /// The elements at `(row, column)` and `(column, row)` differ.
+    Asymmetric {
+        /// Row index of the element, which is smaller than `column`
+        row: usize,
+        /// Column index of the element
+        column: usize,
+    },
+    /// The off-diagonal element at `(row, column)` is negative.
+    Negative {
+        /// Row index of the element
+        row: usize,
+        /// Column index of the element
+        column: usize,
+    },
+    /// The indices violate the ultrametric inequality `a[(i, j)] >= min(a[(i, k)], a[(k, j)])`.
+    Triple {
+        /// First index of the violated inequality, which is in the left partition of the split
+        i: usize,
+        /// Second index of the violated inequality, which is in the right partition of the split
+        j: usize,
+        /// Pivot of the violated inequality, which is outside of the split partition, such that `a[(i, k)]` and `a[(k, j)]` are at the level of the parent partition
+        k: usize,
+    },
+    /// The element at `(row, column)` differs from the `level` of the off-diagonal block containing it.
+    Block {
+        /// Row index of the element, which is in the left partition of the block
+        row: usize,
+        /// Column index of the element, which is in the right partition of the block
+        column: usize,
+        /// Level of the block, i.e. the minimal element between the left and the right partition
+        level: f64,
+    },
+}
+
+/// Report of the check of the ultrametric property by [`check_ultrametric`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct UltrametricReport {
+    /// First violation found or `None`, if the matrix is ultrametric
+    pub violation: Option<UltrametricViolation>,
+    /// Maximal magnitude of all violations found, which is `0` if the matrix is ultrametric
+    pub max_violation: f64,
+}
+
+impl UltrametricReport {
+    /// Returns `true` if no violation was found.
+    pub fn is_ultrametric(&self) -> bool {
+        return self.violation.is_none();
     }
-    let idx: Vec<usize> = (0..matrix.nrows()).collect();
-    if !is_submatrix_ultrametric(matrix, idx, 0.0) {
-        return false;
+
+    /// Record a violation with magnitude `magnitude`, where only the first violation is kept.
+    fn record(&mut self, violation: UltrametricViolation, magnitude: f64) {
+        if self.violation.is_none() {
+            self.violation = Some(violation);
+        }
+        self.max_violation = f64::max(self.max_violation, magnitude);
     }
-    return true;
 }
 
-fn is_symmetric(matrix: &DMatrix<f64>) -> bool {
-    if !matrix.is_square() {
-        return false;
+/// Checks if a matrix is ultrametric and reports the first violation.
+///
+/// The check is done in the same way as in [`is_ultrametric`]. First, the matrix is checked to be square and free of NaN, in which case the magnitude of the violation is infinite. Then, all pairs of elements are checked for symmetry, where the magnitude is the absolute difference. Finally, the matrix is partitioned in the same way as in `from_matrix` for `UltrametricTree`. If the level of a partition is smaller than the level of its parent, then the partition contains a triple violating the ultrametric inequality, or a negative element for the first partition, and the magnitude is the difference of the levels. Each element of an off-diagonal block that differs from the level of the block is a violation, whose magnitude is the absolute difference. The partitioning continues after violations, such that `max_violation` is the maximum over all pairs of elements.
+///
+/// # Example:
+/// ```
+/// let not_ultrametric = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+///     vec![0.0, 1.0, 3.0, 2.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0, 2.0, 1.0, 1.0, 1.0]);
+/// let report = ultrametric_matrix_tools::utils::check_ultrametric(&not_ultrametric);
+///
+/// assert_eq!(report.violation,
+///     Some(ultrametric_matrix_tools::utils::UltrametricViolation::Block { row: 2, column: 3, level: 2.0 }));
+/// assert_eq!(report.max_violation, 1.0);
+/// ```
+pub fn check_ultrametric(matrix: &DMatrix<f64>) -> UltrametricReport {
+    return find_violations(matrix, false);
+}
+
+/// Search the violations of the ultrametric property as described in [`check_ultrametric`], where the search stops at the first violation if `first_only` is `true`, which is used by [`is_ultrametric`].
+fn find_violations(matrix: &DMatrix<f64>, first_only: bool) -> UltrametricReport {
+    let mut report = UltrametricReport {
+        violation: None,
+        max_violation: 0.,
+    };
+    let (rows, columns) = matrix.shape();
+    if rows != columns {
+        report.record(
+            UltrametricViolation::NonSquare { rows, columns },
+            f64::INFINITY,
+        );
+        return report;
     }
-    let size = matrix.shape().0;
-    for i in 1..size {
-        for j in (i + 1)..size {
-            if matrix[(i, j)] != matrix[(j, i)] {
-                return false;
+    for row in 0..rows {
+        for column in 0..columns {
+            if matrix[(row, column)].is_nan() {
+                report.record(
+                    UltrametricViolation::NotANumber { row, column },
+                    f64::INFINITY,
+                );
+                return report;
             }
         }
     }
-    return true;
-}
-
-fn is_submatrix_ultrametric(matrix: &DMatrix<f64>, idx: Vec<usize>, prev_value: f64) -> bool {
-    let first_i = idx[0];
-    if idx.len() == 1 {
-        return true;
+    for row in 0..rows {
+        for column in (row + 1)..columns {
+            let difference = (matrix[(row, column)] - matrix[(column, row)]).abs();
+            if difference > 0.0 {
+                report.record(UltrametricViolation::Asymmetric { row, column }, difference);
+                if first_only {
+                    return report;
+                }
+            }
+        }
     }
-    let mut left_partition: Vec<usize> = vec![first_i];
-    let mut right_partition: Vec<usize> = Vec::new();
-    let mut min = f64::MAX;
-    for &i in &idx[1..] {
-        if min > matrix[(first_i, i)] {
-            min = matrix[(first_i, i)];
-            left_partition.extend(right_partition.iter());
-            right_partition.clear();
-            right_partition.push(i);
-        } else {
-            if min == matrix[(first_i, i)] {
-                right_partition.push(i);
-            } else {
-                left_partition.push(i);
+
+    let mut stack: Vec<(Vec<usize>, f64, Option<usize>)> = vec![((0..rows).collect(), 0.0, None)];
+    while let Some((idx, prev_value, outside_k)) = stack.pop() {
+        if idx.len() <= 1 {
+            continue;
+        }
+        let first_i = idx[0];
+        let (min, left_partition, right_partition) = split_partition(matrix, &idx);
+        let first_j = right_partition[0];
+        if min < prev_value {
+            let violation = match outside_k {
+                Some(k) => UltrametricViolation::Triple {
+                    i: first_i,
+                    j: first_j,
+                    k,
+                },
+                None => UltrametricViolation::Negative {
+                    row: first_i,
+                    column: first_j,
+                },
+            };
+            report.record(violation, prev_value - min);
+            if first_only {
+                return report;
             }
         }
+        for &row in left_partition.iter() {
+            for &column in right_partition.iter() {
+                let difference = (matrix[(row, column)] - min).abs();
+                if difference > 0.0 {
+                    report.record(
+                        UltrametricViolation::Block {
+                            row,
+                            column,
+                            level: min,
+                        },
+                        difference,
+                    );
+                    if first_only {
+                        return report;
+                    }
+                }
+            }
+        }
+
+        stack.push((right_partition, min, Some(first_i)));
+        stack.push((left_partition, min, Some(first_j)));
     }
-    if min < prev_value || !is_block_equal(matrix, &left_partition, &right_partition, min) {
+    return report;
+}
+
+/// Checks if a matrix is ultrametric up to a tolerance.
+///
+/// The matrix is partitioned in the same way as in `from_matrix_approx` for `UltrametricTree` with `eps = abs_tol`. Each element of an off-diagonal block, in both orientations, may differ from the level of the block by at most `abs_tol + rel_tol * |level|` and the level of a partition may be smaller than the level of its parent by at most the same tolerance of the parent level. Thus, if the matrix is accepted, then the tree constructed by `from_matrix_approx` with `eps = abs_tol` reproduces each element of the matrix within the tolerance. Non-square matrices and matrices containing NaN are rejected.
+///
+/// # Example:
+/// ```
+/// let matrix = ultrametric_matrix_tools::na::DMatrix::from_vec(4, 4,
+///     vec![0.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 1.0, 3.0, 1.0, 5.0, 1.0 + 1e-12, 1.0, 1.0, 1.0 + 1e-12, 1.0]);
+///
+/// assert_eq!(ultrametric_matrix_tools::utils::is_ultrametric(&matrix), false);
+/// assert_eq!(ultrametric_matrix_tools::utils::is_ultrametric_with_tolerance(&matrix, 0.0, 1e-9), true);
+/// ```
+pub fn is_ultrametric_with_tolerance(matrix: &DMatrix<f64>, abs_tol: f64, rel_tol: f64) -> bool {
+    if !matrix.is_square() || matrix.iter().any(|value| value.is_nan()) {
         return false;
     }
+    let tolerance = |level: f64| abs_tol + rel_tol * level.abs();
+    let mut stack: Vec<(Vec<usize>, f64)> = vec![((0..matrix.nrows()).collect(), 0.0)];
+    while let Some((idx, prev_value)) = stack.pop() {
+        if idx.len() <= 1 {
+            continue;
+        }
+        let (min, left_partition, right_partition) = split_partition_approx(matrix, &idx, abs_tol);
+        if min < prev_value - tolerance(prev_value)
+            || !is_block_equal(
+                matrix,
+                &left_partition,
+                &right_partition,
+                min,
+                tolerance(min),
+            )
+        {
+            return false;
+        }
 
-    let left = is_submatrix_ultrametric(matrix, left_partition, min);
-    let right = is_submatrix_ultrametric(matrix, right_partition, min);
-    return left && right;
+        stack.push((right_partition, min));
+        stack.push((left_partition, min));
+    }
+    return true;
 }
 
 fn is_block_equal(
     matrix: &DMatrix<f64>,
-    left: &Vec<usize>,
-    right: &Vec<usize>,
+    left: &[usize],
+    right: &[usize],
     value: f64,
+    tolerance: f64,
 ) -> bool {
+    let is_close = |element: f64| element == value || (element - value).abs() <= tolerance;
     for &i in left.iter() {
         for &j in right.iter() {
-            if matrix[(i, j)] != value {
+            if !is_close(matrix[(i, j)]) || !is_close(matrix[(j, i)]) {
                 return false;
             }
         }
@@ -139,6 +375,59 @@ fn is_block_equal(
     return true;
 }
 
+/// Splits a partition of the indices of a matrix into the left and right partition as in `from_matrix` for `UltrametricTree`.
+///
+/// The right partition consists of the indices, where the first row of the partition has its minimal value, and the left partition of all other indices. The minimal value is returned together with both partitions.
+pub(crate) fn split_partition(
+    matrix: &DMatrix<f64>,
+    partition: &[usize],
+) -> (f64, Vec<usize>, Vec<usize>) {
+    let first_i = partition[0];
+    let mut left_partition: Vec<usize> = vec![first_i];
+    let mut right_partition: Vec<usize> = Vec::new();
+    let mut min = f64::INFINITY;
+    for &i in &partition[1..] {
+        if min > matrix[(first_i, i)] {
+            min = matrix[(first_i, i)];
+            left_partition.extend(right_partition.iter());
+            right_partition.clear();
+            right_partition.push(i);
+        } else if min == matrix[(first_i, i)] {
+            right_partition.push(i);
+        } else {
+            left_partition.push(i);
+        }
+    }
+    return (min, left_partition, right_partition);
+}
+
+/// Splits a partition of the indices of a matrix into the left and right partition as in `from_matrix_approx` for `UltrametricTree`.
+///
+/// The right partition consists of the indices, where the first row of the partition is at most its minimal value plus `eps`, and the left partition of all other indices. The minimal value is returned together with both partitions.
+pub(crate) fn split_partition_approx(
+    matrix: &DMatrix<f64>,
+    partition: &[usize],
+    eps: f64,
+) -> (f64, Vec<usize>, Vec<usize>) {
+    let first_i = partition[0];
+    let mut left_partition: Vec<usize> = vec![first_i];
+    let mut right_partition: Vec<usize> = Vec::new();
+    let mut min = f64::INFINITY;
+    for &i in &partition[1..] {
+        if matrix[(first_i, i)] < min {
+            min = matrix[(first_i, i)];
+        }
+    }
+    for &i in &partition[1..] {
+        if matrix[(first_i, i)] <= min + eps {
+            right_partition.push(i);
+        } else {
+            left_partition.push(i);
+        }
+    }
+    return (min, left_partition, right_partition);
+}
+
 /// Constructs a random ultrametric matrix
 ///
 /// The elements of the ultrametric matrix have an integer value between `1` and `size - 1`.